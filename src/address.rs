@@ -0,0 +1,121 @@
+//! Addresses: short, checksummed identifiers for public keys.
+//!
+//! An address is the Base58Check encoding of a version byte followed by HASH160 of the public key
+//! (RIPEMD-160 of SHA-256 of its encoded bytes, which start with the key's signature scheme). Coins are sent to the hash; the full public key
+//! is only revealed when they are spent, and the 4 byte checksum catches mistyped addresses.
+use ripemd::Ripemd160;
+use crate::signing::PublicKey;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+
+/// Version byte of addresses on this chain, every address starts with 'S'
+pub const ADDRESS_VERSION: u8 = 63;
+
+/// Length of the public key hash
+const HASH_LENGTH: usize = 20;
+
+/// Length of the checksum at the end of an address
+const CHECKSUM_LENGTH: usize = 4;
+
+/// Errors found while parsing an address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressError {
+    /// The string contains characters outside the Base58 alphabet
+    InvalidBase58,
+    /// The decoded address has the wrong number of bytes
+    InvalidLength(usize),
+    /// The checksum doesn't match, usually a typo
+    InvalidChecksum,
+    /// The address belongs to another network or format
+    UnknownVersion(u8),
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressError::InvalidBase58 => write!(f, "address is not valid Base58"),
+            AddressError::InvalidLength(length) => write!(f, "address decodes to {} bytes", length),
+            AddressError::InvalidChecksum => write!(f, "address checksum does not match"),
+            AddressError::UnknownVersion(version) => write!(f, "unknown address version {}", version),
+        }
+    }
+}
+
+impl std::error::Error for AddressError {}
+
+/// Struct representing a validated address, kept in its Base58Check form
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Address(String);
+
+impl Address {
+    /// Constructor for the address of a public key of any scheme
+    pub fn from_public_key(public_key: &PublicKey) -> Self {
+        let hash: [u8; HASH_LENGTH] = Ripemd160::digest(Sha256::digest(public_key.to_bytes())).into();
+
+        let mut payload = Vec::with_capacity(1 + HASH_LENGTH + CHECKSUM_LENGTH);
+        payload.push(ADDRESS_VERSION);
+        payload.extend_from_slice(&hash);
+        let checksum = checksum(&payload);
+        payload.extend_from_slice(&checksum);
+        Address(bs58::encode(payload).into_string())
+    }
+
+    /// Constructor validating an address typed or pasted by a user
+    pub fn parse(text: &str) -> Result<Self, AddressError> {
+        let bytes = bs58::decode(text).into_vec().map_err(|_| AddressError::InvalidBase58)?;
+        if bytes.len() != 1 + HASH_LENGTH + CHECKSUM_LENGTH {
+            return Err(AddressError::InvalidLength(bytes.len()));
+        }
+
+        let (payload, stored_checksum) = bytes.split_at(1 + HASH_LENGTH);
+        if stored_checksum != checksum(payload) {
+            return Err(AddressError::InvalidChecksum);
+        }
+        if payload[0] != ADDRESS_VERSION {
+            return Err(AddressError::UnknownVersion(payload[0]));
+        }
+        Ok(Address(text.to_string()))
+    }
+
+    /// Method to get the Base58Check text of the address
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Function to compute the checksum of an address payload: the start of its double SHA-256
+fn checksum(payload: &[u8]) -> [u8; CHECKSUM_LENGTH] {
+    let digest = Sha256::digest(Sha256::digest(payload));
+    [digest[0], digest[1], digest[2], digest[3]]
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for Address {
+    type Err = AddressError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Address::parse(text)
+    }
+}
+
+impl TryFrom<String> for Address {
+    type Error = AddressError;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        Address::parse(&text)
+    }
+}
+
+impl From<Address> for String {
+    fn from(address: Address) -> Self {
+        address.0
+    }
+}
@@ -0,0 +1,327 @@
+//! Blocks and block headers.
+//!
+//! A block commits to its transactions through their Merkle root, and its header is everything
+//! needed to check the proof-of-work, so light clients only have to keep headers.
+use crate::chain::{ChainError, MAX_BLOCK_TRANSACTIONS}; // For reporting inconsistent blocks and the block size limit
+use crate::codec::{Decode, DecodeError, Encode, Reader}; // For the canonical binary encoding
+use crate::difficulty::Target; // For checking proof-of-work
+use crate::merkle::{merkle_root, MerkleProof}; // For committing to and proving transactions
+use crate::mining::{CancelToken, Miner, MiningError, MiningOutcome, MiningReport}; // For multi-threaded proof-of-work
+use crate::transaction::Transaction; // For the transactions included in a block
+use crate::current_timestamp; // For block timestamps
+use serde::{Deserialize, Serialize}; // For converting blocks to and from formats like JSON
+use sha2::{Digest, Sha256}; // For block hashes
+use std::collections::HashSet; // For finding duplicate transactions
+
+/// Struct representing a block in the blockchain
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Block {
+    /// Position of the block in the chain
+    pub index: u64,
+    /// Time the block was created
+    pub timestamp: u128,
+    /// Hash of the previous block
+    pub previous_hash: [u8; 32],
+    /// Merkle root of the transaction hashes, commits every byte of the transactions to the hash
+    pub merkle_root: [u8; 32],
+    /// Proof-of-work target in compact form
+    pub bits: u32,
+    /// Current block's hash
+    pub hash: [u8; 32],
+    /// Number used for mining (proof of work)
+    pub nonce: u64,
+    /// Transactions included in the block
+    pub transactions: Vec<Transaction>,
+}
+
+/// Struct representing a block header, everything a light client needs to check a block's hash
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockHeader {
+    /// Position of the block in the chain
+    pub index: u64,
+    /// Time the block was created
+    pub timestamp: u128,
+    /// Hash of the previous block
+    pub previous_hash: [u8; 32],
+    /// Merkle root of the block's transaction hashes
+    pub merkle_root: [u8; 32],
+    /// Proof-of-work target in compact form
+    pub bits: u32,
+    /// Number used for mining (proof of work)
+    pub nonce: u64,
+    /// Block hash claimed by the header
+    pub hash: [u8; 32],
+}
+
+/// Implementation of the BlockHeader struct
+impl BlockHeader {
+    /// Method to encode the fields covered by the hash, everything but the hash itself
+    fn encode_hashed_fields(&self, out: &mut Vec<u8>) {
+        self.index.encode_to(out);
+        self.timestamp.encode_to(out);
+        self.previous_hash.encode_to(out);
+        self.merkle_root.encode_to(out);
+        self.bits.encode_to(out);
+        self.nonce.encode_to(out);
+    }
+
+    /// Method to calculate the hash of the header
+    pub fn calculate_hash(&self) -> [u8; 32] {
+        let mut data = vec![];
+        self.encode_hashed_fields(&mut data);
+        Sha256::digest(&data).into()
+    }
+
+    /// Method to check that the hash satisfies the header's proof-of-work target
+    pub fn meets_target(&self) -> bool {
+        Target::from_compact(self.bits).is_met_by(&self.hash)
+    }
+
+    /// Method to get the work the header represents, the expected number of hashes to mine it
+    pub fn work(&self) -> num_bigint::BigUint {
+        Target::from_compact(self.bits).work()
+    }
+
+    /// Method to verify that a transaction is included in the block, using only this header
+    pub fn verify_inclusion(&self, proof: &MerkleProof) -> bool {
+        // The header must hash to the hash it claims, otherwise its Merkle root can't be trusted
+        self.hash == self.calculate_hash() && self.meets_target() && proof.verify(&self.merkle_root)
+    }
+}
+
+/// Implementation of the Block struct
+impl Block {
+    /// Constructor for creating a new block
+    pub fn new(index: u64, previous_hash: [u8; 32], transactions: Vec<Transaction>, bits: u32) -> Block {
+        // Get current timestamp
+        let timestamp = current_timestamp();
+        
+        // Initialize an empty hash and nonce
+        let hash = [0; 32];
+        let nonce = 0;
+
+        // Commit to the transactions through their Merkle root
+        let leaves: Vec<[u8; 32]> = transactions.iter().map(Transaction::hash).collect();
+        let merkle_root = merkle_root(&leaves);
+        
+        // Create the block and calculate its hash
+        let mut block = Block { 
+            index,
+            timestamp,
+            previous_hash,
+            merkle_root,
+            bits,
+            hash,
+            nonce,
+            transactions,
+        };
+        block.hash = block.calculate_hash();
+        block // Still has to be mined, see mine_block
+    }
+
+    /// Method to get the header of the block
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            index: self.index,
+            timestamp: self.timestamp,
+            previous_hash: self.previous_hash,
+            merkle_root: self.merkle_root,
+            bits: self.bits,
+            nonce: self.nonce,
+            hash: self.hash,
+        }
+    }
+
+    /// Method to calculate the hash of the block (the hash of its header)
+    pub fn calculate_hash(&self) -> [u8; 32] {
+        self.header().calculate_hash()
+    }
+
+    /// Method to get the ids of the block's transactions
+    pub fn transaction_ids(&self) -> Vec<String> {
+        self.transactions.iter().map(|transaction| transaction.id()).collect()
+    }
+
+    /// Method to get the hashes of the block's transactions, the leaves of its Merkle tree
+    pub fn transaction_hashes(&self) -> Vec<[u8; 32]> {
+        self.transactions.iter().map(Transaction::hash).collect()
+    }
+
+    /// Method to build an inclusion proof for the transaction with id `tx_id` in this block
+    ///
+    /// The proof's leaf is the transaction's hash, which the holder of the transaction can check
+    pub fn merkle_proof(&self, tx_id: &str) -> Option<MerkleProof> {
+        let index = self.transactions.iter().position(|transaction| transaction.id() == tx_id)?;
+        MerkleProof::new(&self.transaction_hashes(), index)
+    }
+
+    /// Method to mine the block (find a hash that meets its target) on the miner's worker threads
+    ///
+    /// Returns the report of the job that found the hash, with its hash rate
+    ///
+    /// Fails with MiningError::Cancelled if the job was cancelled before a hash was found
+    pub fn mine_block(&mut self, miner: &Miner, cancel: &CancelToken) -> Result<MiningReport, MiningError> {
+        loop {
+            let report = miner.mine(&self.header(), cancel);
+            match report.outcome {
+                MiningOutcome::Found { nonce, hash } => {
+                    self.nonce = nonce;
+                    self.hash = hash;
+                    return Ok(report);
+                }
+                MiningOutcome::Cancelled => return Err(MiningError::Cancelled { hashes: report.hashes }),
+                // Every nonce failed with this timestamp, a later one gives a fresh search space
+                MiningOutcome::Exhausted => self.timestamp = current_timestamp().max(self.timestamp + 1),
+            }
+        }
+    }
+
+    /// Method to check that the block is internally consistent: no duplicate transactions, Merkle root, size limit, hash and proof-of-work
+    ///
+    /// Whether the target itself is right depends on the chain, see Blockchain::bits_at
+    pub fn validate(&self) -> Result<(), ChainError> {
+        // An odd Merkle level pairs its last node with itself, so repeating the last transactions gives the same
+        // root; no valid block contains a transaction twice, so refusing duplicates keeps every root unambiguous
+        let mut seen = HashSet::new();
+        if let Some(duplicate) = self.transaction_ids().into_iter().find(|tx_id| !seen.insert(tx_id.clone())) {
+            return Err(ChainError::DuplicateTransaction { index: self.index, tx_id: duplicate });
+        }
+
+        // The transactions must match the committed Merkle root
+        if self.merkle_root != merkle_root(&self.transaction_hashes()) {
+            return Err(ChainError::MerkleRootMismatch { index: self.index });
+        }
+
+        // Block space is limited, only the coinbase comes on top
+        let count = self.transactions.iter().filter(|transaction| !transaction.is_coinbase()).count();
+        if count > MAX_BLOCK_TRANSACTIONS {
+            return Err(ChainError::TooManyTransactions { index: self.index, count });
+        }
+
+        // The stored hash must match the header contents
+        if self.hash != self.calculate_hash() {
+            return Err(ChainError::HashMismatch { index: self.index });
+        }
+
+        // The hash must satisfy the proof-of-work
+        if !self.header().meets_target() {
+            return Err(ChainError::InsufficientWork { index: self.index });
+        }
+        Ok(())
+    }
+}
+
+impl Encode for BlockHeader {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.encode_hashed_fields(out);
+        self.hash.encode_to(out);
+    }
+}
+
+impl Decode for BlockHeader {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        Ok(BlockHeader {
+            index: u64::decode_from(reader)?,
+            timestamp: u128::decode_from(reader)?,
+            previous_hash: <[u8; 32]>::decode_from(reader)?,
+            merkle_root: <[u8; 32]>::decode_from(reader)?,
+            bits: u32::decode_from(reader)?,
+            nonce: u64::decode_from(reader)?,
+            hash: <[u8; 32]>::decode_from(reader)?,
+        })
+    }
+}
+
+/// A block is encoded as its header followed by its transactions
+impl Encode for Block {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.header().encode_to(out);
+        self.transactions.encode_to(out);
+    }
+}
+
+impl Decode for Block {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let header = BlockHeader::decode_from(reader)?;
+        Ok(Block {
+            index: header.index,
+            timestamp: header.timestamp,
+            previous_hash: header.previous_hash,
+            merkle_root: header.merkle_root,
+            bits: header.bits,
+            hash: header.hash,
+            nonce: header.nonce,
+            transactions: Vec::decode_from(reader)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::difficulty::POW_LIMIT_BITS;
+    use crate::signing::SchemeId;
+    use crate::wallet::Wallet;
+
+    // Function to mine a block at the easiest target holding a coinbase and a signed transfer
+    fn mined_block() -> Block {
+        let sender = Wallet::new(SchemeId::Ed25519).expect("key generation");
+        let receiver = Wallet::new(SchemeId::Secp256k1).expect("key generation");
+        let transfer = Transaction::new(sender.private_key(), &receiver.address(), 10, 1, 0).expect("signing");
+        let coinbase = Transaction::coinbase(&receiver.address(), 50, 1);
+        let mut block = Block::new(1, [7; 32], vec![coinbase, transfer], POW_LIMIT_BITS);
+        block.mine_block(&Miner::new(1), &CancelToken::new()).expect("not cancelled");
+        block
+    }
+
+    #[test]
+    fn blocks_round_trip() {
+        let block = mined_block();
+        let bytes = block.encode();
+        let decoded = Block::decode(&bytes).expect("valid encoding");
+        assert_eq!(decoded.encode(), bytes);
+        assert_eq!(decoded, block);
+        assert_eq!(BlockHeader::decode(&block.header().encode()), Ok(block.header()));
+        assert_eq!(decoded.validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_non_canonical_blocks() {
+        let block = mined_block();
+
+        let mut trailing = block.encode();
+        trailing.push(0);
+        assert_eq!(Block::decode(&trailing), Err(DecodeError::TrailingBytes(1)));
+
+        // The transfer's replaceable flag is the byte before its signature (a u32 length and 64 bytes)
+        let mut bad_flag = block.encode();
+        let flag = bad_flag.len() - 64 - 4 - 1;
+        assert_eq!(bad_flag[flag], 0);
+        bad_flag[flag] = 2;
+        assert_eq!(Block::decode(&bad_flag), Err(DecodeError::InvalidTag(2)));
+    }
+
+    #[test]
+    fn header_proves_inclusion() {
+        let block = mined_block();
+        let header = block.header();
+        for transaction in &block.transactions {
+            let proof = block.merkle_proof(&transaction.id()).expect("transaction is in the block");
+            assert!(header.verify_inclusion(&proof));
+        }
+        assert_eq!(block.merkle_proof("unknown"), None);
+
+        // A header that doesn't hash to its claimed hash proves nothing
+        let proof = block.merkle_proof(&block.transactions[1].id()).expect("transaction is in the block");
+        let forged = BlockHeader { nonce: header.nonce.wrapping_add(1), ..header };
+        assert!(!forged.verify_inclusion(&proof));
+    }
+
+    #[test]
+    fn rejects_duplicate_transactions() {
+        let mut block = mined_block();
+        let duplicate = block.transactions[1].clone();
+        block.transactions.push(duplicate);
+        assert!(matches!(block.validate(), Err(ChainError::DuplicateTransaction { index: 1, .. })));
+    }
+}
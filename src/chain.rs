@@ -0,0 +1,1466 @@
+//! The blockchain: validates and connects blocks, keeps the ledger of balances and the pending transactions.
+//!
+//! Every block passes the same consensus rules whether it was mined here, received from elsewhere or
+//! replayed from the block files, and nothing changes unless it passes all of them.
+//! Blocks form a tree: every block with a valid header is kept, and the active chain is the branch
+//! with the most cumulative work. When another branch overtakes it, the chain reorganizes: blocks are
+//! disconnected back to the fork point using their undo data, the new branch is connected, and the
+//! transactions of the disconnected blocks go back to the mempool.
+use crate::address::Address; // For checksummed addresses derived from public keys
+use crate::block::{Block, BlockHeader}; // For the blocks of the chain
+use crate::codec::{Decode, DecodeError, Encode, Reader}; // For the canonical binary encoding
+use crate::difficulty::RetargetParams; // For adjusting proof-of-work targets
+use crate::emission::EmissionSchedule; // For the block subsidy schedule
+use crate::mempool::Mempool; // For the pending transactions
+use crate::merkle::MerkleProof; // For proving transactions
+use crate::mining::{CancelToken, Miner, MiningError, MiningReport}; // For multi-threaded proof-of-work
+use crate::storage::{BlockStore, Record}; // For persisting the chain to disk
+use crate::{current_timestamp, to_hex};
+use crate::transaction::{Transaction, TransactionError}; // For the transfers recorded in blocks
+use crate::utxo::{UTXOPool, UtxoUndo}; // For the unspent outputs ledger
+use num_bigint::BigUint; // For cumulative chain work
+use serde::{Deserialize, Serialize}; // For converting credits to and from formats like JSON
+use sha2::{Digest, Sha256}; // For credit ids
+use std::collections::{HashMap, VecDeque}; // For tracking account nonces and queueing transactions
+use std::fmt; // For displaying validation errors
+use std::io; // For block store errors
+use std::path::Path; // For the block store directory
+
+/// Struct representing the blockchain itself
+#[derive(Debug)]
+pub struct Blockchain {
+    /// Active chain, the known branch with the most work, from genesis to the tip
+    chain: Vec<Block>,
+    /// Undo data of every block of the active chain, to disconnect it again
+    undo: Vec<BlockUndo>,
+    /// Every block with a valid header by hash, on the active chain or a side branch
+    tree: HashMap<[u8; 32], TreeEntry>,
+    /// Blocks of side branches, which can become active in a reorganization
+    side_blocks: HashMap<[u8; 32], Block>,
+    /// Number of blocks added to the tree so far, gives each one its arrival order
+    blocks_received: u64,
+    /// Compact target of the genesis block, later blocks follow the retargeting rules
+    initial_bits: u32,
+    /// Rules for adjusting the difficulty from block timestamps
+    retarget: RetargetParams,
+    /// Proof-of-work miner used for this node's blocks
+    miner: Miner,
+    /// Transactions waiting to be added to a block
+    mempool: Mempool,
+    /// Block subsidy paid to miners at each height
+    emission: EmissionSchedule,
+    /// Unspent outputs, the single source of truth for balances
+    ledger: UTXOPool,
+    /// Next nonce expected from each sender in a block
+    nonces: HashMap<String, u64>,
+    /// Total coins mined
+    total_mined: u64,
+    /// Coins credited outside of block transactions, needed to replay balances
+    credits: Vec<Credit>,
+    /// Total coins credited, counted against TOTAL_SUPPLY along with total_mined
+    total_credited: u64,
+    /// Block files the chain is persisted to, None keeps it in memory only
+    store: Option<BlockStore>,
+}
+
+/// Struct representing a block in the block tree
+#[derive(Debug, Clone)]
+struct TreeEntry {
+    /// Header of the block
+    header: BlockHeader,
+    /// Work of the block and all its ancestors
+    chain_work: BigUint,
+    /// Order the block was received in; of branches with equal work the first one received wins
+    sequence: u64,
+    /// The block or one of its ancestors broke a consensus rule when connected
+    invalid: bool,
+}
+
+/// Struct representing what connecting a block changed, so it can be disconnected again
+#[derive(Debug, Clone)]
+struct BlockUndo {
+    /// Outputs spent and created by the block and the credits applied after it
+    utxos: UtxoUndo,
+    /// Nonce of each sender the block changed, from before the block
+    nonces: Vec<(String, Option<u64>)>,
+    /// Coins the block issued
+    minted: u64,
+}
+
+/// How connect_block placed a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectOutcome {
+    /// The block extended the active chain
+    Extended,
+    /// The block's branch overtook the active chain, which lost this many blocks
+    Reorganized { disconnected: usize },
+    /// The block was kept on a branch with no more work than the active chain
+    SideBranch,
+}
+
+/// Struct representing coins credited outside of block transactions (initial funding)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Credit {
+    /// Height of the block after which the credit is applied, on whichever branch is active
+    pub block_index: u64,
+    /// Position among all credits, so equal credits still have different ids
+    pub sequence: u64,
+    /// Address that received the coins
+    pub address: String,
+    /// Amount credited
+    pub amount: u64,
+}
+
+impl Credit {
+    /// Method to get the credit's id, the hash of its encoding, which its ledger output is recorded under
+    pub fn id(&self) -> String {
+        to_hex(&Sha256::digest(self.encode()))
+    }
+}
+
+
+/// Errors found while validating a block or the whole chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainError {
+    /// Block index doesn't match its position in the chain
+    InvalidIndex { index: u64 },
+    /// previous_hash doesn't match the hash of the previous block
+    BrokenLink { index: u64 },
+    /// Transactions don't match the committed Merkle root
+    MerkleRootMismatch { index: u64 },
+    /// The block contains a transaction twice
+    DuplicateTransaction { index: u64, tx_id: String },
+    /// The block has more than MAX_BLOCK_TRANSACTIONS besides its coinbase
+    TooManyTransactions { index: u64, count: usize },
+    /// Stored hash doesn't match the block header
+    HashMismatch { index: u64 },
+    /// Hash doesn't meet the difficulty
+    InsufficientWork { index: u64 },
+    /// Target doesn't follow the retargeting rules
+    UnexpectedDifficulty { index: u64 },
+    /// Timestamp isn't after the median time past of its branch
+    TimestampTooEarly { index: u64 },
+    /// Timestamp is more than MAX_FUTURE_BLOCK_TIME ahead of this node's clock
+    TimestampTooFarAhead { index: u64 },
+    /// Transaction signature doesn't verify
+    InvalidSignature { index: u64, tx_id: String },
+    /// Coinbase is missing, misplaced, or pays more than the reward plus fees
+    InvalidCoinbase { index: u64 },
+    /// Transaction nonce is reused or out of order
+    InvalidNonce { index: u64, tx_id: String },
+    /// Transaction can't be applied to the replayed ledger
+    InvalidTransfer { index: u64, tx_id: String },
+    /// Replayed balance differs from stored state
+    BalanceMismatch { address: String, expected: u64, actual: u64 },
+    /// Replayed unspent outputs differ from the stored ledger
+    UtxoSetMismatch,
+    /// Replayed nonce differs from stored state
+    NonceMismatch { address: String },
+    /// Replayed coin issuance differs from total_mined
+    SupplyMismatch { expected: u64, actual: u64 },
+    /// The recorded credits add up to a different amount than total_credited
+    CreditMismatch { expected: u64, actual: u64 },
+    /// Coins mined and credited add up to more than TOTAL_SUPPLY
+    SupplyExceeded { issued: u64 },
+    /// The block's previous block isn't known
+    UnknownParent { index: u64 },
+    /// The block builds on a block that broke a consensus rule
+    InvalidAncestor { index: u64 },
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainError::InvalidIndex { index } => write!(f, "block {}: index does not match its position", index),
+            ChainError::BrokenLink { index } => write!(f, "block {}: previous hash does not match", index),
+            ChainError::MerkleRootMismatch { index } => write!(f, "block {}: Merkle root mismatch", index),
+            ChainError::DuplicateTransaction { index, tx_id } => write!(f, "block {}: transaction {} included twice", index, tx_id),
+            ChainError::TooManyTransactions { index, count } => {
+                write!(f, "block {}: {} transactions, at most {} allowed", index, count, MAX_BLOCK_TRANSACTIONS)
+            }
+            ChainError::HashMismatch { index } => write!(f, "block {}: hash mismatch", index),
+            ChainError::InsufficientWork { index } => write!(f, "block {}: insufficient proof of work", index),
+            ChainError::UnexpectedDifficulty { index } => write!(f, "block {}: unexpected difficulty", index),
+            ChainError::TimestampTooEarly { index } => write!(f, "block {}: timestamp not after the median time past", index),
+            ChainError::TimestampTooFarAhead { index } => write!(f, "block {}: timestamp too far in the future", index),
+            ChainError::InvalidSignature { index, tx_id } => write!(f, "block {}: invalid signature on transaction {}", index, tx_id),
+            ChainError::InvalidCoinbase { index } => write!(f, "block {}: invalid coinbase transaction", index),
+            ChainError::InvalidNonce { index, tx_id } => write!(f, "block {}: transaction {} reuses or skips a nonce", index, tx_id),
+            ChainError::InvalidTransfer { index, tx_id } => write!(f, "block {}: transaction {} can't be applied", index, tx_id),
+            ChainError::BalanceMismatch { address, expected, actual } => {
+                write!(f, "balance of {} is {} but the chain gives {}", address, actual, expected)
+            }
+            ChainError::UtxoSetMismatch => write!(f, "unspent outputs do not match the chain"),
+            ChainError::NonceMismatch { address } => write!(f, "nonce of {} does not match the chain", address),
+            ChainError::SupplyMismatch { expected, actual } => {
+                write!(f, "total mined is {} but the chain gives {}", actual, expected)
+            }
+            ChainError::CreditMismatch { expected, actual } => {
+                write!(f, "total credited is {} but the credits add up to {}", actual, expected)
+            }
+            ChainError::SupplyExceeded { issued } => {
+                write!(f, "{} coins mined and credited, more than the total supply of {}", issued, TOTAL_SUPPLY)
+            }
+            ChainError::UnknownParent { index } => write!(f, "block {}: previous block is unknown", index),
+            ChainError::InvalidAncestor { index } => write!(f, "block {}: builds on an invalid block", index),
+        }
+    }
+}
+
+impl std::error::Error for ChainError {}
+
+impl ChainError {
+    /// Method to check whether the error is about the block the header commits to, rather than the copy received
+    ///
+    /// The header commits to every byte of the transactions (see Transaction::hash), so once the body matches
+    /// the Merkle root and hash, any rule it breaks makes the block invalid whoever sends it
+    pub fn is_committed_by_header(&self) -> bool {
+        // A duplicated transaction can give the Merkle root of a valid block, see Block::validate
+        !matches!(
+            self,
+            ChainError::MerkleRootMismatch { .. } | ChainError::HashMismatch { .. } | ChainError::DuplicateTransaction { .. }
+        )
+    }
+}
+
+/// Reasons a block isn't connected to the chain
+#[derive(Debug)]
+pub enum BlockError {
+    /// The block breaks a consensus rule
+    Invalid(ChainError),
+    /// The block was received before
+    AlreadyKnown,
+    /// The block is valid but couldn't be written to the block files
+    Storage(io::Error),
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockError::Invalid(error) => write!(f, "invalid block: {}", error),
+            BlockError::AlreadyKnown => write!(f, "block already known"),
+            BlockError::Storage(error) => write!(f, "could not store the block: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for BlockError {}
+
+impl From<ChainError> for BlockError {
+    fn from(error: ChainError) -> Self {
+        BlockError::Invalid(error)
+    }
+}
+
+impl From<BlockError> for io::Error {
+    fn from(error: BlockError) -> Self {
+        match error {
+            BlockError::Storage(error) => error,
+            error => io::Error::new(io::ErrorKind::InvalidData, error),
+        }
+    }
+}
+
+/// Reasons coins weren't credited
+#[derive(Debug)]
+pub enum CreditError {
+    /// The credit would take the coins mined and credited past TOTAL_SUPPLY
+    SupplyExceeded { requested: u64, remaining: u64 },
+    /// The credit couldn't be written to the block files
+    Storage(io::Error),
+}
+
+impl fmt::Display for CreditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CreditError::SupplyExceeded { requested, remaining } => {
+                write!(f, "cannot credit {} coins, only {} of the total supply remain", requested, remaining)
+            }
+            CreditError::Storage(error) => write!(f, "could not store the credit: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for CreditError {}
+
+/// Define the total supply limit for the coin
+pub const TOTAL_SUPPLY: u64 = 21_000_000;
+
+/// Number of blocks whose median timestamp a new block's timestamp must exceed
+pub const MEDIAN_TIME_SPAN: usize = 11;
+
+/// How far a block's timestamp may be ahead of this node's clock, in milliseconds
+pub const MAX_FUTURE_BLOCK_TIME: u128 = 2 * 60 * 60 * 1000;
+
+/// Define the maximum number of transactions in a block, not counting the coinbase
+///
+/// A consensus rule, so block space stays scarce and transactions compete for it by fee rate;
+/// pending transactions beyond this wait for a later block
+pub const MAX_BLOCK_TRANSACTIONS: usize = 100;
+
+/// Implementation of the Blockchain struct
+impl Blockchain {
+    /// Constructor for creating a new blockchain
+    pub fn new(initial_bits: u32, emission: EmissionSchedule, retarget: RetargetParams) -> Self {
+        let mut blockchain = Blockchain::empty(initial_bits, emission, retarget, None);
+        let _ = blockchain.create_genesis_block(false); // Nothing is stored, and a freshly mined genesis block is always valid
+        blockchain
+    }
+
+    /// Constructor for a blockchain persisted in the block files of `dir`
+    ///
+    /// Reloads and re-validates the stored chain, or starts a new one if the directory is empty
+    pub fn open(dir: impl AsRef<Path>, initial_bits: u32, emission: EmissionSchedule, retarget: RetargetParams) -> io::Result<Self> {
+        let (store, records) = BlockStore::open(dir)?;
+        let mut blockchain = Blockchain::empty(initial_bits, emission, retarget, Some(store));
+        if records.is_empty() {
+            blockchain.create_genesis_block(true)?; // Nothing stored yet, start a new chain
+            return Ok(blockchain);
+        }
+
+        // Replay the records in the order they were received, which makes the same fork choices again
+        for record in records {
+            match record {
+                Record::Block(block) => blockchain.replay_block(block)?,
+                Record::Credit(credit) => blockchain.replay_credit(credit),
+            }
+        }
+
+        // Don't trust the files, the replayed state must pass the same checks as a live chain
+        blockchain
+            .validate_chain()
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        Ok(blockchain)
+    }
+
+    /// Constructor for a blockchain without any blocks
+    fn empty(initial_bits: u32, emission: EmissionSchedule, retarget: RetargetParams, store: Option<BlockStore>) -> Self {
+        Blockchain {
+            chain: vec![], // Initialize with an empty chain
+            undo: vec![], // Initialize with no undo data
+            tree: HashMap::new(), // Initialize with no known blocks
+            side_blocks: HashMap::new(), // Initialize with no side branches
+            blocks_received: 0, // Initialize with no blocks received
+            initial_bits,
+            retarget,
+            miner: Miner::default(), // One mining thread per core
+            mempool: Mempool::default(), // Initialize with no pending transactions
+            emission,
+            ledger: UTXOPool::new(), // Initialize with no unspent outputs
+            nonces: HashMap::new(), // Initialize with no confirmed transactions
+            total_mined: 0, // Initialize total mined coins to zero
+            credits: vec![], // Initialize with no credits
+            total_credited: 0, // Initialize total credited coins to zero
+            store,
+        }
+    }
+
+    /// Method to create the genesis block, the root of the block tree
+    fn create_genesis_block(&mut self, persist: bool) -> Result<(), BlockError> {
+        // Create a block with index 0, no transactions, and an all-zero previous hash
+        let mut genesis_block = Block::new(0, [0; 32], vec![], self.initial_bits);
+        // Mine it; nothing can cancel the genesis block, so mining always finds a hash
+        let _ = genesis_block.mine_block(&self.miner, &CancelToken::new());
+        let hash = genesis_block.hash;
+        self.add_to_tree(genesis_block, persist)?;
+        self.activate_best_chain(hash).map(|_| ()) // Add it to the chain
+    }
+
+    /// Method to get the latest block in the chain, None before the genesis block is added
+    pub fn get_latest_block(&self) -> Option<&Block> {
+        self.chain.last()
+    }
+
+    /// Method to get the blocks of the chain, from genesis to the tip
+    pub fn blocks(&self) -> &[Block] {
+        &self.chain
+    }
+
+    /// Method to get the hash of the block at `height` of the active chain
+    pub fn hash_at(&self, height: u64) -> Option<[u8; 32]> {
+        self.chain.get(usize::try_from(height).ok()?).map(|block| block.hash)
+    }
+
+    /// Method to get the height of a block from its hash, None unless it's on the active chain
+    pub fn height_of(&self, hash: &[u8; 32]) -> Option<u64> {
+        self.tree.get(hash).filter(|_| self.is_active(hash)).map(|entry| entry.header.index)
+    }
+
+    /// Method to read the block at `height` of the active chain back from the block files
+    ///
+    /// The store indexes blocks by hash, since side branches share heights; the active chain resolves the height
+    pub fn read_block_at(&self, height: u64) -> io::Result<Option<Block>> {
+        match (&self.store, self.hash_at(height)) {
+            (Some(store), Some(hash)) => store.read_block(&hash),
+            _ => Ok(None),
+        }
+    }
+
+    /// Method to get the pending transactions
+    pub fn mempool(&self) -> &Mempool {
+        &self.mempool
+    }
+
+    /// Method to get the block files the chain is persisted to, None if it's kept in memory only
+    pub fn store(&self) -> Option<&BlockStore> {
+        self.store.as_ref()
+    }
+
+    /// Method to get the compact target the block at `height` of the active chain must be mined at
+    ///
+    /// Only blocks below `height` are used, so it also works while replaying the chain
+    pub fn bits_at(&self, height: u64) -> u32 {
+        match height.checked_sub(1).and_then(|previous| self.chain.get(previous as usize)) {
+            Some(previous) => self.bits_after(&previous.hash),
+            None => self.initial_bits,
+        }
+    }
+
+    /// Method to get the compact target of a block built on `parent_hash`, following the retargeting rules of the parent's own branch
+    pub fn bits_after(&self, parent_hash: &[u8; 32]) -> u32 {
+        let previous = match self.tree.get(parent_hash) {
+            Some(entry) => &entry.header,
+            None => return self.initial_bits, // Only the genesis block has no parent
+        };
+        let height = previous.index + 1;
+        if !self.retarget.is_retarget_height(height) {
+            return previous.bits; // Unchanged within a retarget period
+        }
+
+        // Measure from the last block before the period, or from genesis for the first period
+        let first = self.ancestor(parent_hash, height.saturating_sub(self.retarget.interval + 1)).unwrap_or(previous);
+        let blocks = previous.index - first.index;
+        let timespan = previous.timestamp.saturating_sub(first.timestamp);
+        self.retarget.retarget(previous.bits, blocks, timespan)
+    }
+
+    /// Method to get the header of the ancestor at `height` of a known block
+    fn ancestor(&self, hash: &[u8; 32], height: u64) -> Option<&BlockHeader> {
+        if self.is_active(hash) {
+            return self.chain.get(height as usize).and_then(|block| self.tree.get(&block.hash)).map(|entry| &entry.header);
+        }
+        let mut entry = self.tree.get(hash)?;
+        while entry.header.index > height {
+            entry = self.tree.get(&entry.header.previous_hash)?;
+        }
+        Some(&entry.header)
+    }
+
+    /// Method to get the median timestamp of a known block and the blocks before it, up to MEDIAN_TIME_SPAN of them
+    ///
+    /// A block built on it must have a later timestamp, so timestamps can't be moved back to make retargeting easier
+    pub fn median_time_past(&self, hash: &[u8; 32]) -> u128 {
+        let mut timestamps = vec![];
+        let mut cursor = self.tree.get(hash);
+        while let Some(entry) = cursor.filter(|_| timestamps.len() < MEDIAN_TIME_SPAN) {
+            timestamps.push(entry.header.timestamp);
+            cursor = self.tree.get(&entry.header.previous_hash);
+        }
+        timestamps.sort_unstable();
+        timestamps.get(timestamps.len() / 2).copied().unwrap_or(0)
+    }
+
+    /// Method to check whether a known block is on the active chain
+    fn is_active(&self, hash: &[u8; 32]) -> bool {
+        self.tree
+            .get(hash)
+            .and_then(|entry| self.chain.get(entry.header.index as usize))
+            .is_some_and(|block| block.hash == *hash)
+    }
+
+    /// Method to get the cumulative work of the chain, the expected number of hashes behind it
+    pub fn chain_work(&self) -> num_bigint::BigUint {
+        self.chain.iter().map(|block| block.header().work()).sum()
+    }
+
+    /// Method to add a block to the tree after checking everything that doesn't depend on the ledger:
+    /// a known, valid parent, its index, the difficulty and timestamp rules of its branch, its Merkle root, hash and proof-of-work
+    ///
+    /// With `persist`, the block is stored, so a reopened chain sees the same blocks in the same order
+    fn add_to_tree(&mut self, block: Block, persist: bool) -> Result<(), BlockError> {
+        if self.tree.contains_key(&block.hash) {
+            return Err(BlockError::AlreadyKnown);
+        }
+        let index = block.index;
+        let parent_work = if self.tree.is_empty() {
+            // Only the genesis block has no parent
+            if index != 0 || block.previous_hash != [0; 32] {
+                return Err(ChainError::UnknownParent { index }.into());
+            }
+            BigUint::default()
+        } else {
+            let parent = self.tree.get(&block.previous_hash).ok_or(ChainError::UnknownParent { index })?;
+            if parent.invalid {
+                return Err(ChainError::InvalidAncestor { index }.into());
+            }
+            if index != parent.header.index + 1 {
+                return Err(ChainError::InvalidIndex { index }.into());
+            }
+            parent.chain_work.clone()
+        };
+        if block.bits != self.bits_after(&block.previous_hash) {
+            return Err(ChainError::UnexpectedDifficulty { index }.into());
+        }
+
+        // Bounding the timestamp from both sides bounds the timespans retargeting measures
+        if block.timestamp <= self.median_time_past(&block.previous_hash) {
+            return Err(ChainError::TimestampTooEarly { index }.into());
+        }
+        if block.timestamp > current_timestamp().saturating_add(MAX_FUTURE_BLOCK_TIME) {
+            return Err(ChainError::TimestampTooFarAhead { index }.into()); // Not stored, it may be sent again once its time has come
+        }
+        block.validate()?;
+
+        if persist {
+            if let Some(store) = &mut self.store {
+                store.append_block(&block).map_err(BlockError::Storage)?;
+            }
+        }
+        let header = block.header();
+        let chain_work = parent_work + header.work();
+        let sequence = self.blocks_received;
+        self.blocks_received += 1;
+        self.tree.insert(block.hash, TreeEntry { header, chain_work, sequence, invalid: false });
+        self.side_blocks.insert(block.hash, block);
+        Ok(())
+    }
+
+    /// Method to find the valid block with the most cumulative work
+    ///
+    /// On equal work the active tip stays, otherwise the block received first wins
+    fn best_tip(&self) -> Option<[u8; 32]> {
+        let tip = self.get_latest_block().map(|block| block.hash);
+        self.tree
+            .iter()
+            .filter(|(_, entry)| !entry.invalid)
+            .max_by(|(a_hash, a), (b_hash, b)| {
+                a.chain_work
+                    .cmp(&b.chain_work)
+                    .then_with(|| (Some(**a_hash) == tip).cmp(&(Some(**b_hash) == tip)))
+                    .then_with(|| b.sequence.cmp(&a.sequence))
+            })
+            .map(|(hash, _)| *hash)
+    }
+
+    /// Method to make the valid branch with the most work the active chain, after `hash` was added to the tree
+    ///
+    /// Blocks that turn out invalid while being connected are marked invalid with their descendants, and the
+    /// next best branch is tried, so the chain always ends up on a fully valid branch
+    fn activate_best_chain(&mut self, hash: [u8; 32]) -> Result<ConnectOutcome, BlockError> {
+        let original_tip = self.get_latest_block().map(|block| block.hash);
+        let mut disconnected_transactions = vec![];
+        let mut failure = None;
+        while let Some(best) = self.best_tip() {
+            if Some(best) == self.get_latest_block().map(|block| block.hash) {
+                break;
+            }
+
+            // Walk back from the best tip to the active chain to find the branch to connect
+            let mut branch = vec![];
+            let mut cursor = best;
+            while !self.is_active(&cursor) {
+                branch.push(cursor);
+                match self.tree.get(&cursor) {
+                    Some(entry) if entry.header.index > 0 => cursor = entry.header.previous_hash,
+                    _ => break, // A genesis block of its own, the active chain is empty
+                }
+            }
+            branch.reverse();
+
+            // Disconnect the active blocks above the fork point, then connect the branch
+            let fork_height = branch.first().and_then(|first| self.tree.get(first)).map_or(0, |entry| entry.header.index);
+            // Blocks come off tip first, so each one's transactions go in front of those of the blocks above it
+            while self.chain.len() as u64 > fork_height {
+                disconnected_transactions.splice(0..0, self.disconnect_tip());
+            }
+            for block_hash in branch {
+                // Only invalid blocks lose their data, and those are never part of a branch
+                let block = match self.side_blocks.remove(&block_hash) {
+                    Some(block) => block,
+                    None => break,
+                };
+                if let Err(error) = self.connect_tip(block) {
+                    // A body that doesn't match its header says nothing about the block the hash commits to,
+                    // so only that copy is forgotten and the genuine block can still be received
+                    if error.is_committed_by_header() {
+                        self.mark_invalid(&block_hash);
+                    } else {
+                        self.forget_block(&block_hash);
+                    }
+                    failure = Some(error);
+                    break;
+                }
+            }
+        }
+
+        // Count the blocks of the previous active chain that are no longer on it
+        let mut disconnected = 0;
+        let mut cursor = original_tip;
+        while let Some(hash) = cursor.filter(|hash| !self.is_active(hash)) {
+            disconnected += 1;
+            cursor = self.tree.get(&hash).map(|entry| entry.header.previous_hash);
+        }
+        if !disconnected_transactions.is_empty() {
+            self.resubmit_transactions(disconnected_transactions);
+        }
+
+        // The block is gone from the tree if it was forgotten
+        match self.tree.get(&hash) {
+            Some(entry) if !entry.invalid => {}
+            entry => {
+                let index = entry.map_or(0, |entry| entry.header.index);
+                return Err(failure.unwrap_or(ChainError::InvalidAncestor { index }).into());
+            }
+        }
+        if !self.is_active(&hash) {
+            Ok(ConnectOutcome::SideBranch)
+        } else if disconnected > 0 {
+            Ok(ConnectOutcome::Reorganized { disconnected })
+        } else {
+            Ok(ConnectOutcome::Extended)
+        }
+    }
+
+    /// Method to find a known block and every block built on it
+    fn with_descendants(&self, hash: &[u8; 32]) -> Vec<[u8; 32]> {
+        let height = match self.tree.get(hash) {
+            Some(entry) => entry.header.index,
+            None => return vec![],
+        };
+
+        // Parents come before their children in height order, so one pass reaches every descendant
+        let mut higher: Vec<(u64, [u8; 32], [u8; 32])> = self
+            .tree
+            .iter()
+            .filter(|(_, entry)| entry.header.index > height)
+            .map(|(hash, entry)| (entry.header.index, *hash, entry.header.previous_hash))
+            .collect();
+        higher.sort();
+        let mut found = vec![*hash];
+        for (_, hash, parent) in higher {
+            if found.contains(&parent) {
+                found.push(hash);
+            }
+        }
+        found
+    }
+
+    /// Method to mark a block invalid, with every block built on it
+    fn mark_invalid(&mut self, hash: &[u8; 32]) {
+        for hash in self.with_descendants(hash) {
+            if let Some(entry) = self.tree.get_mut(&hash) {
+                entry.invalid = true;
+            }
+            self.side_blocks.remove(&hash);
+        }
+    }
+
+    /// Method to forget a block and every block built on it, so they can be received again
+    fn forget_block(&mut self, hash: &[u8; 32]) {
+        for hash in self.with_descendants(hash) {
+            self.tree.remove(&hash);
+            self.side_blocks.remove(&hash);
+        }
+    }
+
+    /// Method to connect a block on top of the active chain, checking its transactions against the ledger
+    ///
+    /// The credits issued after a block at this height are applied after it; nothing changes unless the block is valid
+    ///
+    /// The changes are recorded as they're made, so the undo data costs as much as the block, not the whole ledger
+    fn connect_tip(&mut self, block: Block) -> Result<(), ChainError> {
+        block.validate()?; // Only a body matching the header can make the header invalid
+
+        // The nonce of every sender in the block before it, to restore if the block is invalid or disconnected
+        let mut nonces: Vec<(String, Option<u64>)> = vec![];
+        for transaction in block.transactions.iter().filter(|transaction| !transaction.is_coinbase()) {
+            if !nonces.iter().any(|(sender, _)| *sender == transaction.sender) {
+                nonces.push((transaction.sender.clone(), self.nonces.get(&transaction.sender).copied()));
+            }
+        }
+
+        // Apply the block to the ledger itself, moved out of the chain so the rules can still read the chain
+        let mut ledger = std::mem::take(&mut self.ledger);
+        let mut current_nonces = std::mem::take(&mut self.nonces);
+        let mut utxos = UtxoUndo::default();
+        let result = self.apply_block_transactions(&block, &mut ledger, &mut current_nonces, &mut utxos);
+        if result.is_err() {
+            ledger.undo(&utxos);
+            restore_nonces(&mut current_nonces, nonces.clone());
+        }
+        self.ledger = ledger;
+        self.nonces = current_nonces;
+        let minted = result?;
+        for credit in self.credits.iter().filter(|credit| credit.block_index == block.index) {
+            utxos.created.push(self.ledger.credit(&credit.id(), &credit.address, credit.amount));
+        }
+
+        // Remove the mined transactions and the ones conflicting with them, the rest stay pending for a later block
+        self.mempool.remove_confirmed(&block.transactions);
+        for (sender, _) in &nonces {
+            self.drop_unaffordable(sender);
+        }
+
+        self.total_mined += minted; // Update total coins mined
+        self.chain.push(block);
+        self.undo.push(BlockUndo { utxos, nonces, minted });
+        Ok(())
+    }
+
+    /// Method to drop the pending transactions of a sender that the ledger no longer allows, with the later ones that depend on them
+    ///
+    /// A block can confirm a different transaction with the same nonce, which leaves less for the ones that follow it
+    fn drop_unaffordable(&mut self, sender: &str) {
+        let mut nonce = self.nonces.get(sender).copied().unwrap_or(0);
+        let mut available = self.balance_of(sender);
+        let stale = self
+            .mempool
+            .transactions_of(sender)
+            .find(|transaction| {
+                let fits = transaction.nonce == nonce && transaction.total_cost() <= available;
+                nonce += 1;
+                available = available.saturating_sub(transaction.total_cost());
+                !fits
+            })
+            .map(|transaction| transaction.nonce);
+        if let Some(stale) = stale {
+            self.mempool.remove_from(sender, stale);
+        }
+    }
+
+    /// Method to disconnect the tip of the active chain using its undo data, keeping it as a side branch
+    ///
+    /// Returns its transactions, which are pending again
+    fn disconnect_tip(&mut self) -> Vec<Transaction> {
+        let (block, undo) = match (self.chain.pop(), self.undo.pop()) {
+            (Some(block), Some(undo)) => (block, undo),
+            _ => return vec![],
+        };
+        self.ledger.undo(&undo.utxos);
+        restore_nonces(&mut self.nonces, undo.nonces);
+        self.total_mined -= undo.minted;
+
+        let transactions = block.transactions.iter().filter(|transaction| !transaction.is_coinbase()).cloned().collect();
+        self.side_blocks.insert(block.hash, block);
+        transactions
+    }
+
+    /// Method to return the transactions of disconnected blocks, in chain order, to the mempool after a reorganization
+    ///
+    /// The pending transactions are resubmitted after them, so every sender's nonces stay in order without gaps;
+    /// whatever the new chain already confirmed or no longer allows is dropped
+    fn resubmit_transactions(&mut self, disconnected: Vec<Transaction>) {
+        let pending = self.mempool.drain();
+        for transaction in disconnected.into_iter().chain(pending) {
+            let _ = self.create_transaction(transaction);
+        }
+    }
+
+    /// Method to replay a block's transactions on a ledger and the senders' nonces, checking every consensus rule
+    ///
+    /// The outputs spent and created are recorded in `utxos`
+    ///
+    /// Returns the coins the block issues; on error the ledger and nonces are left partly updated, `utxos` reverts the ledger
+    fn apply_block_transactions(
+        &self,
+        block: &Block,
+        ledger: &mut UTXOPool,
+        nonces: &mut HashMap<String, u64>,
+        utxos: &mut UtxoUndo,
+    ) -> Result<u64, ChainError> {
+        // Every mined block starts with its coinbase, only the genesis block has none
+        let (coinbase, transactions) = match block.transactions.split_first() {
+            Some((first, rest)) if first.is_coinbase() => (Some(first), rest),
+            _ => (None, &block.transactions[..]),
+        };
+        if coinbase.is_none() && block.index > 0 {
+            return Err(ChainError::InvalidCoinbase { index: block.index });
+        }
+
+        let mut fees: u64 = 0;
+        for transaction in transactions {
+            apply_transaction(block.index, transaction, ledger, nonces, utxos)?;
+            fees = fees
+                .checked_add(transaction.fee)
+                .ok_or_else(|| ChainError::InvalidTransfer { index: block.index, tx_id: transaction.id() })?;
+        }
+
+        // The coinbase may claim at most the block's subsidy plus the fees it collects
+        let coinbase = match coinbase {
+            Some(coinbase) => coinbase,
+            None => return Ok(0),
+        };
+        let subsidy = self.emission.subsidy_at(block.index);
+        let allowed = subsidy.checked_add(fees).ok_or(ChainError::InvalidCoinbase { index: block.index })?;
+        if coinbase.nonce != block.index || coinbase.amount > allowed || Address::parse(&coinbase.receiver).is_err() {
+            return Err(ChainError::InvalidCoinbase { index: block.index });
+        }
+        utxos.created.push(ledger.credit(&coinbase.id(), &coinbase.receiver, coinbase.amount));
+        Ok(coinbase.amount.saturating_sub(fees)) // Fees move existing coins, only the rest is new
+    }
+
+    /// Method to re-add a stored block while reopening the chain
+    fn replay_block(&mut self, block: Block) -> io::Result<()> {
+        let index = block.index;
+        let hash = block.hash;
+        self.add_to_tree(block, false).map_err(|error| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("stored block {} is invalid: {}", index, error))
+        })?;
+        // Blocks are stored once their header is checked, so one found invalid when connected is marked invalid again
+        let _ = self.activate_best_chain(hash);
+        Ok(())
+    }
+
+    /// Method to re-apply a stored credit while reopening the chain
+    fn replay_credit(&mut self, credit: Credit) {
+        if let Some(undo) = self.undo.get_mut(credit.block_index as usize) {
+            undo.utxos.created.push(self.ledger.credit(&credit.id(), &credit.address, credit.amount));
+        }
+        self.total_credited = self.total_credited.saturating_add(credit.amount); // validate_chain reports stored credits past the supply
+        self.credits.push(credit);
+    }
+
+    /// Method to get the balance of an address, derived from its unspent outputs
+    pub fn balance_of(&self, address: &str) -> u64 {
+        self.ledger.balance_of(address)
+    }
+
+    /// Method to get the nonce the next transaction from an address must use
+    ///
+    /// Counts the sender's pending transactions, so several can be queued before a block is mined
+    pub fn next_nonce(&self, address: &str) -> u64 {
+        let confirmed = self.nonces.get(address).copied().unwrap_or(0);
+        confirmed + self.mempool.pending_count(address)
+    }
+
+    /// Method to credit new coins to the ledger and record them for replay
+    fn apply_credit(&mut self, address: String, amount: u64) -> io::Result<()> {
+        let credit = Credit {
+            block_index: self.get_latest_block().map_or(0, |tip| tip.index),
+            sequence: self.credits.len() as u64,
+            address,
+            amount,
+        };
+
+        // Store the credit first, so a reopened chain never misses coins that were handed out
+        if let Some(store) = &mut self.store {
+            store.append_credit(&credit)?;
+        }
+        // Recorded with the tip's undo data, so disconnecting the tip takes the credit back out until its height is reached again
+        let outpoint = self.ledger.credit(&credit.id(), &credit.address, credit.amount);
+        if let Some(undo) = self.undo.last_mut() {
+            undo.utxos.created.push(outpoint);
+        }
+        self.total_credited += credit.amount; // fund_account keeps it within TOTAL_SUPPLY
+        self.credits.push(credit);
+        Ok(())
+    }
+
+    /// Method to credit coins to an address outside of any block (e.g. initial funding)
+    ///
+    /// The coins come out of the supply that hasn't been mined or credited yet
+    ///
+    /// Fails if they'd take the total past TOTAL_SUPPLY or the credit can't be stored, and then nothing is credited
+    pub fn fund_account(&mut self, address: &Address, amount: u64) -> Result<(), CreditError> {
+        let remaining = self.remaining_supply();
+        if amount > remaining {
+            return Err(CreditError::SupplyExceeded { requested: amount, remaining });
+        }
+        self.apply_credit(address.to_string(), amount).map_err(CreditError::Storage)
+    }
+
+    /// Method to get the total coins credited outside of blocks
+    pub fn total_credited(&self) -> u64 {
+        self.total_credited
+    }
+
+    /// Method to get how much of TOTAL_SUPPLY hasn't been mined or credited yet
+    pub fn remaining_supply(&self) -> u64 {
+        TOTAL_SUPPLY.saturating_sub(self.total_mined).saturating_sub(self.total_credited)
+    }
+
+    /// Method to check that the whole chain is internally consistent
+    ///
+    /// Walks every block, then replays all transactions and credits to confirm the stored ledger
+    pub fn validate_chain(&self) -> Result<(), ChainError> {
+        let mut ledger = UTXOPool::new();
+        let mut nonces: HashMap<String, u64> = HashMap::new();
+        let mut total_mined: u64 = 0;
+
+        for (position, block) in self.chain.iter().enumerate() {
+            // Each block must sit at its own index and link to its predecessor
+            if block.index != position as u64 {
+                return Err(ChainError::InvalidIndex { index: position as u64 });
+            }
+            let expected_previous_hash = match position {
+                0 => [0; 32], // The genesis block has no predecessor
+                _ => self.chain[position - 1].hash,
+            };
+            if block.previous_hash != expected_previous_hash {
+                return Err(ChainError::BrokenLink { index: block.index });
+            }
+
+            // Difficulty, timestamp, Merkle root, hash and proof-of-work
+            if block.bits != self.bits_at(block.index) {
+                return Err(ChainError::UnexpectedDifficulty { index: block.index });
+            }
+            if block.timestamp <= self.median_time_past(&block.previous_hash) {
+                return Err(ChainError::TimestampTooEarly { index: block.index });
+            }
+            block.validate()?;
+
+            // Signatures, nonces, transfers and the coinbase, replayed through the same rules as new blocks
+            total_mined += self.apply_block_transactions(block, &mut ledger, &mut nonces, &mut UtxoUndo::default())?;
+
+            // Credits are applied after the transactions of the block they follow
+            for credit in self.credits.iter().filter(|credit| credit.block_index == block.index) {
+                ledger.credit(&credit.id(), &credit.address, credit.amount);
+            }
+        }
+
+        // The replayed balances must match the stored ones
+        let balances = ledger.balances();
+        let stored_balances = self.ledger.balances();
+        let mut addresses: Vec<&String> = balances.keys().chain(stored_balances.keys()).collect();
+        addresses.sort();
+        addresses.dedup();
+        for address in addresses {
+            let expected = balances.get(address).copied().unwrap_or(0);
+            let actual = stored_balances.get(address).copied().unwrap_or(0);
+            if expected != actual {
+                return Err(ChainError::BalanceMismatch { address: address.clone(), expected, actual });
+            }
+        }
+
+        // And so must the individual unspent outputs
+        if ledger != self.ledger {
+            return Err(ChainError::UtxoSetMismatch);
+        }
+
+        // And the nonces of every sender
+        let mut senders: Vec<&String> = nonces.keys().chain(self.nonces.keys()).collect();
+        senders.sort();
+        senders.dedup();
+        for sender in senders {
+            if nonces.get(sender) != self.nonces.get(sender) {
+                return Err(ChainError::NonceMismatch { address: sender.clone() });
+            }
+        }
+
+        // And the coins issued so far
+        if total_mined != self.total_mined {
+            return Err(ChainError::SupplyMismatch { expected: total_mined, actual: self.total_mined });
+        }
+
+        // Credits are counted apart from the blocks, and together with them stay within the total supply
+        let credited = self.credits.iter().fold(0u64, |credited, credit| credited.saturating_add(credit.amount));
+        if credited != self.total_credited {
+            return Err(ChainError::CreditMismatch { expected: credited, actual: self.total_credited });
+        }
+        let issued = total_mined.saturating_add(credited);
+        if issued > TOTAL_SUPPLY {
+            return Err(ChainError::SupplyExceeded { issued });
+        }
+        Ok(())
+    }
+
+    /// Method to find a transaction and prove its inclusion to a client holding only headers
+    pub fn get_transaction_proof(&self, tx_id: &str) -> Option<(BlockHeader, MerkleProof)> {
+        self.chain
+            .iter()
+            .find_map(|block| block.merkle_proof(tx_id).map(|proof| (block.header(), proof)))
+    }
+
+    /// Method to create and add a transaction to the pending transactions
+    ///
+    /// The signature is checked against the key revealed for the sender address, so nobody can spend for another address
+    ///
+    /// Returns the pending transaction it replaced by fee, if any
+    pub fn create_transaction(&mut self, transaction: Transaction) -> Result<Option<Transaction>, TransactionError> {
+        // Drop transactions that were pending for too long before counting what the sender has left
+        let now = current_timestamp();
+        self.mempool.expire(now);
+
+        // Ensure the sender can cover this transaction on top of the ones already pending
+        // A replacement frees what the transaction it replaces would have spent
+        let replaced_cost = self
+            .mempool
+            .get_by_nonce(&transaction.sender, transaction.nonce)
+            .map_or(0, |pending| pending.transaction.total_cost());
+        let pending_spend = self.mempool.pending_spend(&transaction.sender).saturating_sub(replaced_cost);
+        let available_balance = self.balance_of(&transaction.sender).saturating_sub(pending_spend);
+
+        // Verify the transaction signature
+        let sender_public_key = transaction.sender_key().ok_or(TransactionError::InvalidSenderKey)?;
+        Address::parse(&transaction.receiver).map_err(TransactionError::InvalidReceiver)?;
+        if transaction.amount == 0 {
+            return Err(TransactionError::ZeroAmount);
+        }
+        if !transaction.verify(&sender_public_key) {
+            return Err(TransactionError::InvalidSignature);
+        }
+
+        // Confirmed nonces are replays, later ones than the next would leave a gap
+        // A pending nonce is a conflicting spend, which the mempool only accepts as a replace-by-fee
+        let confirmed_nonce = self.nonces.get(&transaction.sender).copied().unwrap_or(0);
+        let expected_nonce = self.next_nonce(&transaction.sender);
+        if transaction.nonce < confirmed_nonce || transaction.nonce > expected_nonce {
+            return Err(TransactionError::InvalidNonce { expected: expected_nonce, actual: transaction.nonce });
+        }
+        if available_balance < transaction.total_cost() {
+            return Err(TransactionError::InsufficientFunds { available: available_balance, required: transaction.total_cost() });
+        }
+        self.mempool.insert(transaction, now).map_err(TransactionError::Rejected) // Add to pending transactions if valid
+    }
+
+    /// Method to choose the pending transactions for the next block, highest fee rate first
+    ///
+    /// A sender's transactions must stay in nonce order, so only the next one of each sender competes
+    ///
+    /// Each one is applied to a copy of the ledger first; one the ledger refuses is left out with the sender's later
+    /// transactions, which depend on it, so a stale mempool entry can never make the block invalid
+    fn select_transactions(&self) -> Vec<Transaction> {
+        let index = self.chain.len() as u64;
+        let mut ledger = self.ledger.clone();
+        let mut nonces = self.nonces.clone();
+        let mut coinbase_amount = self.emission.subsidy_at(index); // The subsidy plus the fees selected so far
+
+        // Queue each sender's pending transactions (the mempool keeps them in nonce order)
+        let mut queues: Vec<VecDeque<&Transaction>> = vec![];
+        let mut queue_of_sender: HashMap<&str, usize> = HashMap::new();
+        for transaction in self.mempool.transactions() {
+            let queue = *queue_of_sender.entry(&transaction.sender).or_insert_with(|| {
+                queues.push(VecDeque::new());
+                queues.len() - 1
+            });
+            queues[queue].push_back(transaction);
+        }
+
+        let mut selected = vec![];
+        while selected.len() < MAX_BLOCK_TRANSACTIONS {
+            // min_by keeps the first of equal fee rates, so ties go to the sender whose address sorts first
+            let best = queues
+                .iter()
+                .enumerate()
+                .filter_map(|(queue, transactions)| transactions.front().map(|transaction| (queue, *transaction)))
+                .min_by(|(_, a), (_, b)| b.cmp_fee_rate(a));
+            let (queue, transaction) = match best {
+                Some(best) => best,
+                None => break, // Every pending transaction is selected or left out
+            };
+            let claimable = coinbase_amount.checked_add(transaction.fee);
+            let applied = claimable.is_some()
+                && apply_transaction(index, transaction, &mut ledger, &mut nonces, &mut UtxoUndo::default()).is_ok();
+            if let (true, Some(claimable)) = (applied, claimable) {
+                coinbase_amount = claimable;
+                selected.push(transaction.clone());
+                queues[queue].pop_front();
+            } else {
+                queues[queue].clear(); // The sender's later transactions can't be mined without this one
+            }
+        }
+        selected
+    }
+
+    /// Method to build the next block from the pending transactions, ready to be mined
+    ///
+    /// Fails with MiningError::NothingToMine if no pending transaction can be applied to the ledger
+    pub fn create_block_template(&self, miner_address: &Address) -> Result<Block, MiningError> {
+        // Pick the transactions paying the most per byte
+        let mut transactions = self.select_transactions();
+        if transactions.is_empty() {
+            return Err(MiningError::NothingToMine);
+        }
+
+        // Get the hash of the latest block to link the new block
+        let previous_hash = self.get_latest_block().map_or([0; 32], |tip| tip.hash);
+
+        // The miner collects the fees of every included transaction
+        let fees = transactions.iter().try_fold(0u64, |fees, transaction| fees.checked_add(transaction.fee));
+
+        // Add the block subsidy, which the emission schedule keeps within the total supply limit
+        let index = self.chain.len() as u64;
+        let subsidy = self.emission.subsidy_at(index); // Zero once the supply is exhausted, the miner only collects fees
+
+        // The block starts with the coinbase transaction paying the miner
+        // select_transactions leaves out any fee that would overflow the coinbase
+        let amount = fees.and_then(|fees| subsidy.checked_add(fees)).ok_or(MiningError::NothingToMine)?;
+        let coinbase = Transaction::coinbase(miner_address, amount, index);
+        transactions.insert(0, coinbase);
+        
+        // Create a new block with the coinbase and the selected transactions
+        let mut block = Block::new(
+            index, // Block index
+            previous_hash, // Previous block hash
+            transactions, // The coinbase and the selected transactions
+            self.bits_at(index), // Target after retargeting
+        );
+
+        // Even with a clock behind the chain's, the block must be later than the median time past
+        block.timestamp = block.timestamp.max(self.median_time_past(&previous_hash) + 1);
+        block.hash = block.calculate_hash();
+        Ok(block)
+    }
+
+    /// Method to add a mined or received block to the block tree, and to the active chain if its branch has the most work
+    ///
+    /// The block is stored once its header is checked; its transactions are checked when it's connected, and if
+    /// its branch overtakes the active chain, the chain reorganizes to it
+    pub fn connect_block(&mut self, new_block: Block) -> Result<ConnectOutcome, BlockError> {
+        let hash = new_block.hash;
+        self.add_to_tree(new_block, true)?;
+        self.activate_best_chain(hash) // The transactions stay pending if the block is rejected
+    }
+
+    /// Method to mine pending transactions and create a new block
+    ///
+    /// Returns the report of the mining job, which holds the new block's hash and the hash rate
+    pub fn mine_pending_transactions(&mut self, miner_address: &Address, cancel: &CancelToken) -> Result<MiningReport, MiningError> {
+        let mut new_block = self.create_block_template(miner_address)?;
+        let report = new_block.mine_block(&self.miner, cancel)?; // If cancelled, a fresh template is needed, e.g. after a competing block arrived
+        self.connect_block(new_block)?;
+        Ok(report)
+    }
+}
+
+/// Function to apply a transaction of the block at `index` (other than its coinbase) to a ledger and the senders' nonces
+///
+/// On error the nonces may be left partly updated, the ledger isn't changed
+fn apply_transaction(
+    index: u64,
+    transaction: &Transaction,
+    ledger: &mut UTXOPool,
+    nonces: &mut HashMap<String, u64>,
+    utxos: &mut UtxoUndo,
+) -> Result<(), ChainError> {
+    // Only the first transaction may create coins
+    if transaction.is_coinbase() {
+        return Err(ChainError::InvalidCoinbase { index });
+    }
+
+    // The revealed key must belong to the sender address and have signed the transaction
+    let signed = transaction.sender_key().is_some_and(|public_key| transaction.verify(&public_key));
+    if !signed {
+        return Err(ChainError::InvalidSignature { index, tx_id: transaction.id() });
+    }
+
+    // Coins sent to a malformed address could never be spent again, and an output worth nothing never needs to be
+    if Address::parse(&transaction.receiver).is_err() || transaction.amount == 0 {
+        return Err(ChainError::InvalidTransfer { index, tx_id: transaction.id() });
+    }
+
+    // Each sender's nonces must follow on from the previous one
+    let expected_nonce = nonces.entry(transaction.sender.clone()).or_insert(0);
+    if transaction.nonce != *expected_nonce {
+        return Err(ChainError::InvalidNonce { index, tx_id: transaction.id() });
+    }
+    *expected_nonce += 1;
+
+    // Apply the transfer through the ledger engine, which refuses overspends
+    if ledger.transfer(&transaction.sender, &transaction.receiver, transaction.amount, transaction.fee, utxos).is_none() {
+        return Err(ChainError::InvalidTransfer { index, tx_id: transaction.id() });
+    }
+    Ok(())
+}
+
+/// Function to put back the nonces senders had before a block
+fn restore_nonces(nonces: &mut HashMap<String, u64>, previous: Vec<(String, Option<u64>)>) {
+    for (sender, nonce) in previous {
+        match nonce {
+            Some(nonce) => nonces.insert(sender, nonce),
+            None => nonces.remove(&sender),
+        };
+    }
+}
+
+impl Encode for Credit {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.block_index.encode_to(out);
+        self.sequence.encode_to(out);
+        self.address.encode_to(out);
+        self.amount.encode_to(out);
+    }
+}
+
+impl Decode for Credit {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        Ok(Credit {
+            block_index: u64::decode_from(reader)?,
+            sequence: u64::decode_from(reader)?,
+            address: String::decode_from(reader)?,
+            amount: u64::decode_from(reader)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::difficulty::POW_LIMIT_BITS;
+    use crate::emission::TailEmission;
+    use crate::signing::SchemeId;
+    use crate::wallet::Wallet;
+
+    // Function to make an in-memory chain at the easiest target, so mining takes no time
+    fn test_chain() -> Blockchain {
+        let emission = EmissionSchedule::converging_to(TOTAL_SUPPLY, 210_000, TailEmission::None);
+        Blockchain::new(POW_LIMIT_BITS, emission, RetargetParams::new(1000, 10_000, 4))
+    }
+
+    // Function to make `N` wallets, alternating signature schemes
+    fn wallets<const N: usize>() -> [Wallet; N] {
+        std::array::from_fn(|i| {
+            let scheme = if i % 2 == 0 { SchemeId::Ed25519 } else { SchemeId::Secp256k1 };
+            Wallet::new(scheme).expect("key generation")
+        })
+    }
+
+    // Function to mine a block holding a coinbase and `transactions` on any known block, and connect it
+    fn mine_on(blockchain: &mut Blockchain, parent_hash: [u8; 32], miner_address: &Address, transactions: Vec<Transaction>) -> (Block, ConnectOutcome) {
+        let index = blockchain.tree[&parent_hash].header.index + 1;
+        let fees: u64 = transactions.iter().map(|transaction| transaction.fee).sum();
+        let coinbase = Transaction::coinbase(miner_address, blockchain.emission.subsidy_at(index) + fees, index);
+        let mut block = Block::new(index, parent_hash, [vec![coinbase], transactions].concat(), blockchain.bits_after(&parent_hash));
+        block.timestamp = block.timestamp.max(blockchain.median_time_past(&parent_hash) + 1);
+        block.mine_block(&Miner::new(1), &CancelToken::new()).expect("not cancelled");
+        let copy = Block::decode(&block.encode()).expect("valid encoding");
+        let outcome = blockchain.connect_block(block).expect("valid block");
+        (copy, outcome)
+    }
+
+    #[test]
+    fn reorganization_restores_the_ledger_and_nonces() {
+        let mut blockchain = test_chain();
+        let [alice, bob, miner, rival] = wallets();
+        let genesis = blockchain.blocks()[0].hash;
+        blockchain.fund_account(&alice.address(), 100).expect("in memory");
+        let funded_ledger = blockchain.ledger.clone();
+
+        // Alice pays Bob in block 1 of the active chain
+        let payment = Transaction::new(alice.private_key(), &bob.address(), 10, 1, 0).expect("signing");
+        blockchain.create_transaction(payment.clone()).expect("valid transaction");
+        blockchain.mine_pending_transactions(&miner.address(), &CancelToken::new()).expect("mined");
+        let block_reward = blockchain.emission.subsidy_at(1) + payment.fee;
+        assert_eq!(blockchain.balance_of(alice.address().as_str()), 89);
+        assert_eq!(blockchain.balance_of(bob.address().as_str()), 10);
+        assert_eq!(blockchain.balance_of(miner.address().as_str()), block_reward);
+        assert_eq!(blockchain.nonces.get(alice.address().as_str()), Some(&1));
+        assert!(blockchain.mempool().is_empty());
+
+        // A rival branch from genesis ties, then overtakes the block with the payment
+        let (rival_first, outcome) = mine_on(&mut blockchain, genesis, &rival.address(), vec![]);
+        assert_eq!(outcome, ConnectOutcome::SideBranch);
+        assert_eq!(blockchain.balance_of(alice.address().as_str()), 89);
+        let (rival_second, outcome) = mine_on(&mut blockchain, rival_first.hash, &rival.address(), vec![]);
+        assert_eq!(outcome, ConnectOutcome::Reorganized { disconnected: 1 });
+        assert_eq!(blockchain.get_latest_block().map(|block| block.hash), Some(rival_second.hash));
+
+        // The payment is undone and pending again, and the ledger is the funded one plus the rival's coinbases
+        assert_eq!(blockchain.balance_of(alice.address().as_str()), 100);
+        assert_eq!(blockchain.balance_of(bob.address().as_str()), 0);
+        assert_eq!(blockchain.balance_of(miner.address().as_str()), 0);
+        assert_eq!(blockchain.nonces.get(alice.address().as_str()), None);
+        assert_eq!(blockchain.next_nonce(alice.address().as_str()), 1);
+        assert!(blockchain.mempool().contains(&payment.id()));
+        let mut expected_ledger = funded_ledger;
+        for block in [&rival_first, &rival_second] {
+            expected_ledger.credit(&block.transactions[0].id(), rival.address().as_str(), block.transactions[0].amount);
+        }
+        assert_eq!(blockchain.ledger, expected_ledger);
+        assert_eq!(blockchain.total_mined, rival_first.transactions[0].amount + rival_second.transactions[0].amount);
+
+        // Mining again confirms the payment on the new branch
+        blockchain.mine_pending_transactions(&miner.address(), &CancelToken::new()).expect("mined");
+        assert_eq!(blockchain.balance_of(alice.address().as_str()), 89);
+        assert_eq!(blockchain.balance_of(bob.address().as_str()), 10);
+        assert_eq!(blockchain.nonces.get(alice.address().as_str()), Some(&1));
+        assert_eq!(blockchain.validate_chain(), Ok(()));
+    }
+
+    #[test]
+    fn connected_block_drops_pending_transactions_the_sender_can_no_longer_afford() {
+        let mut blockchain = test_chain();
+        let [alice, bob, miner, peer] = wallets();
+        let genesis = blockchain.blocks()[0].hash;
+        blockchain.fund_account(&alice.address(), 100).expect("in memory");
+        blockchain.fund_account(&bob.address(), 100).expect("in memory");
+
+        // Alice queues two payments, then a peer's block confirms a bigger one using her first nonce
+        let first = Transaction::new(alice.private_key(), &bob.address(), 10, 1, 0).expect("signing");
+        let second = Transaction::new(alice.private_key(), &bob.address(), 80, 1, 1).expect("signing");
+        let unrelated = Transaction::new(bob.private_key(), &alice.address(), 5, 1, 0).expect("signing");
+        for transaction in [&first, &second, &unrelated] {
+            blockchain.create_transaction(transaction.clone()).expect("valid transaction");
+        }
+        let conflicting = Transaction::new(alice.private_key(), &bob.address(), 60, 1, 0).expect("signing");
+        let (_, outcome) = mine_on(&mut blockchain, genesis, &peer.address(), vec![conflicting]);
+        assert_eq!(outcome, ConnectOutcome::Extended);
+
+        // Her second payment no longer fits in what she has left; Bob's is unaffected
+        assert_eq!(blockchain.balance_of(alice.address().as_str()), 39);
+        assert!(!blockchain.mempool().contains(&first.id()));
+        assert!(!blockchain.mempool().contains(&second.id()));
+        assert!(blockchain.mempool().contains(&unrelated.id()));
+
+        // So she can send again, and the node can still mine
+        assert_eq!(blockchain.next_nonce(alice.address().as_str()), 1);
+        let retry = Transaction::new(alice.private_key(), &bob.address(), 30, 1, 1).expect("signing");
+        blockchain.create_transaction(retry).expect("valid transaction");
+        blockchain.mine_pending_transactions(&miner.address(), &CancelToken::new()).expect("mined");
+        assert_eq!(blockchain.balance_of(alice.address().as_str()), 13);
+        assert!(blockchain.mempool().is_empty());
+        assert_eq!(blockchain.validate_chain(), Ok(()));
+    }
+
+    #[test]
+    fn block_template_leaves_out_transactions_the_ledger_refuses() {
+        let mut blockchain = test_chain();
+        let [alice, bob, miner] = wallets();
+        blockchain.fund_account(&alice.address(), 100).expect("in memory");
+        blockchain.fund_account(&bob.address(), 10).expect("in memory");
+
+        // Entries the chain would refuse, slipped past create_transaction: Bob overspends, and his next nonce depends on it
+        let overspend = Transaction::new(bob.private_key(), &alice.address(), 50, 1, 0).expect("signing");
+        let dependent = Transaction::new(bob.private_key(), &alice.address(), 5, 1, 1).expect("signing");
+        for transaction in [&overspend, &dependent] {
+            blockchain.mempool.insert(transaction.clone(), current_timestamp()).expect("accepted");
+        }
+        let payment = Transaction::new(alice.private_key(), &bob.address(), 10, 1, 0).expect("signing");
+        blockchain.create_transaction(payment.clone()).expect("valid transaction");
+
+        let template = blockchain.create_block_template(&miner.address()).expect("something to mine");
+        assert_eq!(template.transaction_ids()[1..], [payment.id()]);
+        blockchain.mine_pending_transactions(&miner.address(), &CancelToken::new()).expect("mined");
+        assert_eq!(blockchain.balance_of(bob.address().as_str()), 20);
+
+        // Once only refused transactions are left, there's nothing to mine
+        assert!(blockchain.mempool().contains(&overspend.id()));
+        assert!(matches!(blockchain.create_block_template(&miner.address()), Err(MiningError::NothingToMine)));
+    }
+
+    #[test]
+    fn credits_are_counted_against_the_total_supply() {
+        let mut blockchain = test_chain();
+        let [alice, miner] = wallets();
+        let genesis = blockchain.blocks()[0].hash;
+        blockchain.fund_account(&alice.address(), 100).expect("in memory");
+        mine_on(&mut blockchain, genesis, &miner.address(), vec![]);
+        let remaining = TOTAL_SUPPLY - blockchain.total_mined - 100;
+        assert_eq!(blockchain.remaining_supply(), remaining);
+
+        // A credit past the supply is refused whole
+        let refused = blockchain.fund_account(&alice.address(), remaining + 1);
+        assert!(matches!(refused, Err(CreditError::SupplyExceeded { requested, remaining: left }) if requested == remaining + 1 && left == remaining));
+        assert_eq!(blockchain.balance_of(alice.address().as_str()), 100);
+
+        blockchain.fund_account(&alice.address(), remaining).expect("in memory");
+        assert_eq!((blockchain.total_credited(), blockchain.remaining_supply()), (100 + remaining, 0));
+        assert_eq!(blockchain.validate_chain(), Ok(()));
+
+        // The credits are counted apart, so a count that disagrees with them is reported
+        blockchain.total_credited -= 1;
+        assert_eq!(blockchain.validate_chain(), Err(ChainError::CreditMismatch { expected: 100 + remaining, actual: 99 + remaining }));
+    }
+
+    #[test]
+    fn block_template_leaves_out_fees_that_would_overflow_the_coinbase() {
+        let mut blockchain = test_chain();
+        let [alice, miner] = wallets();
+        blockchain.ledger.credit("credit-large", &alice.address().to_string(), u64::MAX);
+
+        let payment = Transaction::new(alice.private_key(), &miner.address(), 1, u64::MAX - 1, 0).expect("signing");
+        blockchain.create_transaction(payment.clone()).expect("valid transaction");
+        assert!(matches!(blockchain.create_block_template(&miner.address()), Err(MiningError::NothingToMine)));
+    }
+
+    #[test]
+    fn reorganization_resubmits_transactions_in_nonce_order() {
+        let mut blockchain = test_chain();
+        let [alice, bob, miner, rival] = wallets();
+        let genesis = blockchain.blocks()[0].hash;
+        blockchain.fund_account(&alice.address(), 100).expect("in memory");
+
+        // Alice's first payment is in block 1 and her second in block 2
+        let payments: Vec<Transaction> = (0..2)
+            .map(|nonce| Transaction::new(alice.private_key(), &bob.address(), 10, 1, nonce).expect("signing"))
+            .collect();
+        for payment in &payments {
+            blockchain.create_transaction(payment.clone()).expect("valid transaction");
+            blockchain.mine_pending_transactions(&miner.address(), &CancelToken::new()).expect("mined");
+        }
+        assert_eq!(blockchain.balance_of(bob.address().as_str()), 20);
+
+        // A longer rival branch disconnects both blocks
+        let mut parent = genesis;
+        let mut outcome = ConnectOutcome::SideBranch;
+        for _ in 0..3 {
+            let (block, connected) = mine_on(&mut blockchain, parent, &rival.address(), vec![]);
+            parent = block.hash;
+            outcome = connected;
+        }
+        assert_eq!(outcome, ConnectOutcome::Reorganized { disconnected: 2 });
+
+        // Both payments are pending again, and can be mined again in order
+        for payment in &payments {
+            assert!(blockchain.mempool().contains(&payment.id()));
+        }
+        assert_eq!(blockchain.next_nonce(alice.address().as_str()), 2);
+        blockchain.mine_pending_transactions(&miner.address(), &CancelToken::new()).expect("mined");
+        assert_eq!(blockchain.balance_of(bob.address().as_str()), 20);
+        assert_eq!(blockchain.validate_chain(), Ok(()));
+    }
+
+    #[test]
+    fn active_blocks_are_found_by_height() {
+        let dir = std::env::temp_dir().join(format!("solara-chain-heights-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let emission = EmissionSchedule::converging_to(TOTAL_SUPPLY, 210_000, TailEmission::None);
+        let mut blockchain = Blockchain::open(&dir, POW_LIMIT_BITS, emission, RetargetParams::new(1000, 10_000, 4)).expect("opened");
+        let [miner, rival] = wallets();
+        let genesis = blockchain.hash_at(0).expect("genesis block");
+        let (first, _) = mine_on(&mut blockchain, genesis, &miner.address(), vec![]);
+        assert_eq!(blockchain.height_of(&first.hash), Some(1));
+        assert_eq!(blockchain.read_block_at(1).expect("readable").map(|block| block.hash), Some(first.hash));
+
+        // Once a rival branch takes over, its blocks are the ones at those heights
+        let (rival_first, _) = mine_on(&mut blockchain, genesis, &rival.address(), vec![]);
+        let (rival_second, _) = mine_on(&mut blockchain, rival_first.hash, &rival.address(), vec![]);
+        assert_eq!(blockchain.height_of(&first.hash), None);
+        assert_eq!(blockchain.hash_at(1), Some(rival_first.hash));
+        assert_eq!(blockchain.read_block_at(2).expect("readable"), Some(rival_second));
+        assert_eq!(blockchain.read_block_at(3).expect("readable"), None);
+        std::fs::remove_dir_all(&dir).expect("removed");
+    }
+
+    #[test]
+    fn reorganization_back_restores_the_first_branch() {
+        let mut blockchain = test_chain();
+        let [alice, bob, miner] = wallets();
+        let genesis = blockchain.blocks()[0].hash;
+        blockchain.fund_account(&alice.address(), 100).expect("in memory");
+        let payment = Transaction::new(alice.private_key(), &bob.address(), 10, 1, 0).expect("signing");
+        blockchain.create_transaction(payment).expect("valid transaction");
+        blockchain.mine_pending_transactions(&miner.address(), &CancelToken::new()).expect("mined");
+        let paid_tip = blockchain.blocks()[1].hash;
+        let paid_ledger = blockchain.ledger.clone();
+
+        // The rival branch takes over, then the first branch grows past it again
+        let (rival_first, _) = mine_on(&mut blockchain, genesis, &bob.address(), vec![]);
+        mine_on(&mut blockchain, rival_first.hash, &bob.address(), vec![]);
+        let (extension, _) = mine_on(&mut blockchain, paid_tip, &miner.address(), vec![]);
+        let (_, outcome) = mine_on(&mut blockchain, extension.hash, &miner.address(), vec![]);
+        assert_eq!(outcome, ConnectOutcome::Reorganized { disconnected: 2 });
+
+        // The payment is confirmed again, only the first branch's later coinbases were added
+        assert_eq!(blockchain.balance_of(alice.address().as_str()), 89);
+        assert_eq!(blockchain.balance_of(bob.address().as_str()), 10);
+        assert_eq!(blockchain.nonces.get(alice.address().as_str()), Some(&1));
+        assert!(blockchain.mempool().is_empty());
+        let mut expected_ledger = paid_ledger;
+        for block in &blockchain.blocks()[2..] {
+            expected_ledger.credit(&block.transactions[0].id(), miner.address().as_str(), block.transactions[0].amount);
+        }
+        assert_eq!(blockchain.ledger, expected_ledger);
+        assert_eq!(blockchain.validate_chain(), Ok(()));
+    }
+}
@@ -0,0 +1,314 @@
+//! Canonical binary encoding of chain data, used for hashing, signing, storage and network transfer.
+//!
+//! Integers are fixed-width little-endian; strings, byte strings and lists are prefixed with their
+//! length as a u32; booleans are a 0/1 byte and options carry a 0/1 tag. Every value has exactly one encoding, and decoding
+//! rejects anything else (unknown tags, invalid UTF-8, unsorted maps, trailing bytes), so
+//! decode(encode(x)) == x, and encode(decode(bytes)) == bytes whenever decoding succeeds.
+use std::fmt;
+
+/// Errors found while decoding bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input stops in the middle of a value
+    UnexpectedEnd,
+    /// An option or enum tag that has no meaning
+    InvalidTag(u8),
+    /// A string that isn't valid UTF-8
+    InvalidUtf8,
+    /// A length that doesn't fit in memory
+    InvalidLength(u64),
+    /// Valid bytes that another encoding would produce instead
+    NonCanonical(&'static str),
+    /// Bytes left over after the value
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            DecodeError::InvalidTag(tag) => write!(f, "invalid tag {}", tag),
+            DecodeError::InvalidUtf8 => write!(f, "invalid UTF-8 in string"),
+            DecodeError::InvalidLength(length) => write!(f, "invalid length {}", length),
+            DecodeError::NonCanonical(reason) => write!(f, "non-canonical encoding: {}", reason),
+            DecodeError::TrailingBytes(count) => write!(f, "{} trailing bytes", count),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Types with a canonical binary encoding
+pub trait Encode {
+    /// Method to append the encoding of the value to `out`
+    fn encode_to(&self, out: &mut Vec<u8>);
+
+    /// Method to get the encoding of the value
+    fn encode(&self) -> Vec<u8> {
+        let mut out = vec![];
+        self.encode_to(&mut out);
+        out
+    }
+}
+
+/// Types that can be read back from their canonical encoding
+pub trait Decode: Sized {
+    /// Method to read one value from the reader
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError>;
+
+    /// Method to decode a value that must use every byte of the input
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(bytes);
+        let value = Self::decode_from(&mut reader)?;
+        reader.finish()?;
+        Ok(value)
+    }
+}
+
+/// Struct representing a cursor over the bytes being decoded
+pub struct Reader<'a> {
+    /// Bytes not read yet
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Constructor for a reader at the start of `data`
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    /// Method to read the next `count` bytes
+    pub fn take(&mut self, count: usize) -> Result<&'a [u8], DecodeError> {
+        if count > self.data.len() {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (taken, rest) = self.data.split_at(count);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    /// Method to read the next `N` bytes as an array
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    /// Method to read a length prefix
+    pub fn length(&mut self) -> Result<usize, DecodeError> {
+        let length = u32::decode_from(self)?;
+        usize::try_from(length).map_err(|_| DecodeError::InvalidLength(length as u64))
+    }
+
+    /// Method to check that every byte was read
+    pub fn finish(self) -> Result<(), DecodeError> {
+        match self.data.len() {
+            0 => Ok(()),
+            remaining => Err(DecodeError::TrailingBytes(remaining)),
+        }
+    }
+}
+
+/// Function to append a length prefix
+///
+/// Lengths past u32::MAX can't be encoded; nothing on the chain comes close
+pub fn encode_length(length: usize, out: &mut Vec<u8>) {
+    u32::try_from(length).expect("length fits in a u32 prefix").encode_to(out);
+}
+
+// Fixed-width little-endian integers
+macro_rules! impl_integer {
+    ($($integer:ty),*) => {
+        $(
+            impl Encode for $integer {
+                fn encode_to(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+            }
+
+            impl Decode for $integer {
+                fn decode_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+                    Ok(<$integer>::from_le_bytes(reader.take_array()?))
+                }
+            }
+        )*
+    };
+}
+
+impl_integer!(u8, u32, u64, u128);
+
+/// Booleans are a single 0/1 byte
+impl Encode for bool {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.push(u8::from(*self));
+    }
+}
+
+impl Decode for bool {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        match u8::decode_from(reader)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+}
+
+/// usize is always encoded as a u64, so the encoding doesn't depend on the platform
+impl Encode for usize {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode_to(out);
+    }
+}
+
+impl Decode for usize {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let value = u64::decode_from(reader)?;
+        usize::try_from(value).map_err(|_| DecodeError::InvalidLength(value))
+    }
+}
+
+/// Fixed-size byte arrays (hashes) are written as is, without a length
+impl<const N: usize> Encode for [u8; N] {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+}
+
+impl<const N: usize> Decode for [u8; N] {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        reader.take_array()
+    }
+}
+
+impl Encode for str {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        encode_length(self.len(), out);
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Encode for String {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.as_str().encode_to(out);
+    }
+}
+
+impl Decode for String {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let length = reader.length()?;
+        let bytes = reader.take(length)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+}
+
+/// Lists are their length followed by every item; a `Vec<u8>` is therefore a length-prefixed byte string
+impl<T: Encode> Encode for [T] {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        encode_length(self.len(), out);
+        for item in self {
+            item.encode_to(out);
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.as_slice().encode_to(out);
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let length = reader.length()?;
+        // Every item takes at least one byte, so a bogus length can't reserve more than the input
+        let mut items = Vec::with_capacity(length.min(reader.data.len()));
+        for _ in 0..length {
+            items.push(T::decode_from(reader)?);
+        }
+        Ok(items)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        match self {
+            None => out.push(0),
+            Some(value) => {
+                out.push(1);
+                value.encode_to(out);
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        match u8::decode_from(reader)? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode_from(reader)?)),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Function to encode a value and check that it decodes back to itself
+    fn round_trip<T: Encode + Decode + PartialEq + fmt::Debug>(value: T) {
+        let bytes = value.encode();
+        assert_eq!(T::decode(&bytes), Ok(value));
+    }
+
+    #[test]
+    fn values_round_trip() {
+        round_trip(0u8);
+        round_trip(u32::MAX);
+        round_trip(0x0102_0304_0506_0708u64);
+        round_trip(u128::MAX - 1);
+        round_trip(usize::MAX);
+        round_trip(true);
+        round_trip(false);
+        round_trip(String::new());
+        round_trip("solara ✓".to_string());
+        round_trip([7u8; 32]);
+        round_trip(vec![1u64, 2, 3]);
+        round_trip(vec![vec![0u8, 255], vec![]]);
+        round_trip(Some("tip".to_string()));
+        round_trip(None::<u64>);
+    }
+
+    #[test]
+    fn integers_are_fixed_width_little_endian() {
+        assert_eq!(0x0102_0304u32.encode(), vec![4, 3, 2, 1]);
+        assert_eq!(1usize.encode(), 1u64.encode());
+        assert_eq!("ab".encode(), vec![2, 0, 0, 0, b'a', b'b']);
+    }
+
+    #[test]
+    fn rejects_unknown_tags() {
+        assert_eq!(bool::decode(&[2]), Err(DecodeError::InvalidTag(2)));
+        assert_eq!(Option::<u8>::decode(&[2, 0]), Err(DecodeError::InvalidTag(2)));
+    }
+
+    #[test]
+    fn rejects_invalid_utf8() {
+        assert_eq!(String::decode(&[2, 0, 0, 0, 0xc3, 0x28]), Err(DecodeError::InvalidUtf8));
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        assert_eq!(u64::decode(&[1, 2, 3]), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(String::decode(&[5, 0, 0, 0, b'a']), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(Vec::<u32>::decode(&[u8::MAX; 4]), Err(DecodeError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn rejects_trailing_bytes() {
+        let mut bytes = 7u32.encode();
+        bytes.push(0);
+        assert_eq!(u32::decode(&bytes), Err(DecodeError::TrailingBytes(1)));
+    }
+}
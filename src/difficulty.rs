@@ -0,0 +1,158 @@
+//! Proof-of-work targets and difficulty retargeting.
+//!
+//! A block hash, read as a 256-bit big-endian number, must be at most the block's target.
+//! Headers store the target in the compact "bits" form; every `interval` blocks the target is scaled
+//! by how long the last period took compared to the target block time.
+use num_bigint::BigUint;
+
+/// Easiest target a block may have, in compact form (any hash below 2^255)
+pub const POW_LIMIT_BITS: u32 = 0x207f_ffff;
+
+/// Struct representing a 256-bit proof-of-work target
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Target(BigUint);
+
+impl Target {
+    /// Constructor decoding the compact form: one exponent byte and a three byte mantissa,
+    /// target = mantissa * 256^(exponent - 3)
+    pub fn from_compact(bits: u32) -> Self {
+        let exponent = bits >> 24;
+        let mantissa = bits & 0x007f_ffff; // The sign bit is never set for a target
+        if exponent <= 3 {
+            Target(BigUint::from(mantissa >> (8 * (3 - exponent))))
+        } else {
+            Target(BigUint::from(mantissa) << (8 * (exponent - 3)))
+        }
+    }
+
+    /// Constructor for the target met by hashes starting with `hex_zeros` zero hex digits
+    pub fn from_leading_zeros(hex_zeros: u32) -> Self {
+        let bits = 256u32.saturating_sub(4 * hex_zeros);
+        Target((BigUint::from(1u32) << bits) - 1u32)
+    }
+
+    /// Method to encode the target in compact form, rounding it down to three significant bytes
+    pub fn to_compact(&self) -> u32 {
+        let mut size = self.0.bits().div_ceil(8) as u32; // Length in bytes
+        let mut mantissa = if size <= 3 {
+            self.0.iter_u32_digits().next().unwrap_or(0) << (8 * (3 - size))
+        } else {
+            (&self.0 >> (8 * (size - 3))).iter_u32_digits().next().unwrap_or(0)
+        };
+
+        // The top mantissa bit is a sign bit, so move a set bit into the exponent
+        if mantissa & 0x0080_0000 != 0 {
+            mantissa >>= 8;
+            size += 1;
+        }
+        (size << 24) | mantissa
+    }
+
+    /// Method to check whether a hash meets the target
+    pub fn is_met_by(&self, hash: &[u8; 32]) -> bool {
+        BigUint::from_bytes_be(hash) <= self.0
+    }
+
+    /// Method to get the expected number of hashes needed to meet the target: 2^256 / (target + 1)
+    pub fn work(&self) -> BigUint {
+        (BigUint::from(1u32) << 256u32) / (&self.0 + 1u32)
+    }
+}
+
+/// Struct representing the retargeting rules of the chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetargetParams {
+    /// Number of blocks between adjustments
+    pub interval: u64,
+    /// Desired time between blocks, in milliseconds
+    pub target_block_time: u128,
+    /// Largest factor the target may grow or shrink by at one adjustment
+    pub max_adjustment: u32,
+}
+
+impl RetargetParams {
+    /// Constructor for the retargeting rules
+    pub fn new(interval: u64, target_block_time: u128, max_adjustment: u32) -> Self {
+        RetargetParams {
+            interval: interval.max(1), // Retargeting every zero blocks is meaningless
+            target_block_time,
+            max_adjustment: max_adjustment.max(1), // A factor below one would forbid any change
+        }
+    }
+
+    /// Method to check whether the block at `height` starts a new retarget period
+    pub fn is_retarget_height(&self, height: u64) -> bool {
+        height > 0 && height.is_multiple_of(self.interval)
+    }
+
+    /// Method to compute the compact target of the next period
+    /// `timespan` is the time it took to mine the last `blocks` blocks, in milliseconds
+    pub fn retarget(&self, bits: u32, blocks: u64, timespan: u128) -> u32 {
+        let expected = (blocks as u128 * self.target_block_time).max(1);
+        let factor = self.max_adjustment as u128;
+
+        // Blocks that came too slowly raise the target (easier), too quickly lower it
+        let actual = timespan.clamp(expected / factor, expected.saturating_mul(factor)).max(1);
+        let target = Target(Target::from_compact(bits).0 * actual / expected);
+        target.min(Target::from_compact(POW_LIMIT_BITS)).to_compact()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compact_targets_round_trip() {
+        for bits in [0x1d00_ffff, 0x1b04_04cb, POW_LIMIT_BITS, 0x0312_3456, 0x0212_3400, 0x0112_0000] {
+            assert_eq!(Target::from_compact(bits).to_compact(), bits, "{:#010x}", bits);
+        }
+
+        // Encoding keeps three significant bytes, so it rounds down once, and then is stable
+        let target = Target::from_leading_zeros(4);
+        let bits = target.to_compact();
+        assert_eq!(bits, 0x1f00_ffff);
+        assert!(Target::from_compact(bits) <= target);
+        assert_eq!(Target::from_compact(bits).to_compact(), bits);
+    }
+
+    #[test]
+    fn compact_targets_never_set_the_sign_bit() {
+        // A top mantissa byte of 0x80 or more would read as negative, so it moves into a larger exponent
+        let target = Target(BigUint::from(0x80u32));
+        assert_eq!(target.to_compact(), 0x0200_8000);
+        assert_eq!(Target::from_compact(0x0200_8000), target);
+        let target = Target(BigUint::from(0x8000_0000u32));
+        assert_eq!(target.to_compact(), 0x0500_8000);
+        assert_eq!(Target::from_compact(0x0500_8000), target);
+
+        // A set sign bit in stored bits is ignored rather than making the target negative
+        assert_eq!(Target::from_compact(0x1d80_ffff), Target::from_compact(0x1d00_ffff));
+    }
+
+    #[test]
+    fn retarget_scales_by_the_timespan_within_max_adjustment() {
+        let params = RetargetParams::new(10, 1_000, 4);
+        let bits = 0x1d00_ffff;
+        let target = Target::from_compact(bits).0;
+        let scaled = |numerator: u32, denominator: u32| Target(&target * numerator / denominator).to_compact();
+
+        assert_eq!(params.retarget(bits, 10, 10_000), bits);
+        assert_eq!(params.retarget(bits, 10, 20_000), 0x1d01_fffe);
+        assert_eq!(params.retarget(bits, 10, 5_000), scaled(1, 2));
+
+        // Far too fast or too slow only moves the target by the maximum factor
+        assert_eq!(params.retarget(bits, 10, 0), scaled(1, 4));
+        assert_eq!(params.retarget(bits, 10, 2_500), scaled(1, 4));
+        assert_eq!(params.retarget(bits, 10, u128::MAX), scaled(4, 1));
+        assert_eq!(params.retarget(bits, 10, 40_000), scaled(4, 1));
+    }
+
+    #[test]
+    fn retarget_never_passes_the_pow_limit() {
+        let params = RetargetParams::new(10, 1_000, 4);
+        assert_eq!(params.retarget(POW_LIMIT_BITS, 10, 40_000), POW_LIMIT_BITS);
+        assert_eq!(params.retarget(0x2040_0000, 10, 40_000), POW_LIMIT_BITS);
+        assert_eq!(params.retarget(POW_LIMIT_BITS, 10, 2_500), Target(Target::from_compact(POW_LIMIT_BITS).0 / 4u32).to_compact());
+    }
+}
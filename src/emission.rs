@@ -0,0 +1,169 @@
+//! Emission schedule: the block subsidy is a function of the block height only,
+//!
+//! so every node agrees on the reward of any block and the issued supply never passes the cap.
+//! Coins have no sub-units, so halving rounds down; without a tail, what the rounding left over is
+//! spread over the blocks of the halving eras, earliest first, so the supply still reaches the cap
+//! and the subsidy never goes up.
+
+/// What the subsidy does once halvings have made it small
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TailEmission {
+    /// Keep halving until the subsidy reaches zero, with the rounding remainder added to the halving eras
+    None,
+    /// Never pay less than this per block, until the supply cap is reached
+    Floor(u64),
+}
+
+/// Struct representing the emission schedule of the chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmissionSchedule {
+    /// Subsidy of the first block after genesis
+    pub initial_subsidy: u64,
+    /// Number of blocks between halvings
+    pub halving_interval: u64,
+    /// Behaviour once the subsidy gets small
+    pub tail: TailEmission,
+    /// Cumulative subsidy is never allowed past this
+    pub max_supply: u64,
+}
+
+impl EmissionSchedule {
+    /// Constructor for an explicit schedule
+    pub fn new(initial_subsidy: u64, halving_interval: u64, tail: TailEmission, max_supply: u64) -> Self {
+        EmissionSchedule {
+            initial_subsidy,
+            halving_interval: halving_interval.max(1), // A zero interval would never move past the first era
+            tail,
+            max_supply,
+        }
+    }
+
+    /// Constructor for a schedule whose halvings converge on `max_supply`
+    ///
+    /// The eras pay initial * interval, then half that, and so on, which sums to 2 * initial * interval
+    pub fn converging_to(max_supply: u64, halving_interval: u64, tail: TailEmission) -> Self {
+        let halving_interval = halving_interval.max(1);
+        let initial_subsidy = max_supply / halving_interval.saturating_mul(2);
+        EmissionSchedule::new(initial_subsidy, halving_interval, tail, max_supply)
+    }
+
+    /// Method to get the subsidy of an era, before the rounding remainder and the supply cap are applied
+    fn era_subsidy(&self, era: u64) -> u64 {
+        let halved = self.initial_subsidy.checked_shr(era as u32).unwrap_or(0);
+        match self.tail {
+            TailEmission::None => halved,
+            TailEmission::Floor(floor) => halved.max(floor),
+        }
+    }
+
+    /// Method to get the number of eras before halving brings the subsidy to zero
+    fn halving_eras(&self) -> u64 {
+        (u64::BITS - self.initial_subsidy.leading_zeros()) as u64
+    }
+
+    /// Method to get the share of the rounding remainder paid by blocks 1..=height, without a tail
+    ///
+    /// Every block of the halving eras gets an equal share of what rounding down left short of max_supply,
+    /// and the earliest blocks one coin more each, so the subsidy never goes up from one block to the next
+    fn remainder_at(&self, height: u64) -> u128 {
+        if self.tail != TailEmission::None {
+            return 0; // The tail keeps paying until the cap is reached
+        }
+        let eras = self.halving_eras();
+        let halved: u128 = (0..eras).map(|era| (self.initial_subsidy >> era) as u128 * self.halving_interval as u128).sum();
+        let remainder = (self.max_supply as u128).saturating_sub(halved);
+
+        // At least one era, so a schedule that starts at zero still pays out the supply
+        let blocks = eras.max(1) as u128 * self.halving_interval as u128;
+        let paid = (height as u128).min(blocks);
+        remainder / blocks * paid + paid.min(remainder % blocks)
+    }
+
+    /// Method to get the cumulative subsidy of blocks 1..=height (the genesis block pays nothing)
+    pub fn supply_at(&self, height: u64) -> u64 {
+        let mut supply: u128 = 0;
+        let mut counted: u64 = 0; // Blocks already added to the supply
+        let mut era: u64 = 0;
+        while counted < height && supply < self.max_supply as u128 {
+            let subsidy = self.era_subsidy(era);
+            if subsidy == 0 {
+                break; // Every later era pays nothing either
+            }
+
+            // Once the subsidy stops changing, the remaining blocks can be added in one go
+            let remaining = height - counted;
+            let blocks = if subsidy == self.era_subsidy(era + 1) {
+                remaining
+            } else {
+                remaining.min(self.halving_interval)
+            };
+            supply += blocks as u128 * subsidy as u128;
+            counted += blocks;
+            era += 1;
+        }
+        supply += self.remainder_at(height);
+        supply.min(self.max_supply as u128) as u64
+    }
+
+    /// Method to get the subsidy paid to the miner of the block at `height`
+    pub fn subsidy_at(&self, height: u64) -> u64 {
+        if height == 0 {
+            return 0; // The genesis block has no coinbase
+        }
+        self.supply_at(height) - self.supply_at(height - 1)
+    }
+
+    /// Method to project the supply curve as (height, cumulative supply) points every `step` blocks
+    pub fn supply_curve(&self, until_height: u64, step: u64) -> Vec<(u64, u64)> {
+        (0..=until_height)
+            .step_by(step.max(1) as usize)
+            .map(|height| (height, self.supply_at(height)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subsidy_never_goes_up() {
+        // 71 coins halved over 7 eras of 7 blocks leave 34 of the 1000 unpaid, which the first 34 blocks add
+        let schedule = EmissionSchedule::converging_to(1000, 7, TailEmission::None);
+        let subsidies: Vec<u64> = (1..=60).map(|height| schedule.subsidy_at(height)).collect();
+        assert!(subsidies.windows(2).all(|pair| pair[0] >= pair[1]), "{:?}", subsidies);
+        assert_eq!((subsidies[0], subsidies[33], subsidies[34], subsidies[48], subsidies[49]), (72, 5, 4, 1, 0));
+        assert_eq!(schedule.supply_at(u64::MAX), 1000);
+
+        let schedule = EmissionSchedule::converging_to(21_000_000, 210_000, TailEmission::None);
+        let era_subsidies: Vec<u64> = (0..7).map(|era| schedule.subsidy_at(era * 210_000 + 1)).collect();
+        assert_eq!(era_subsidies, [51, 26, 13, 6, 3, 1, 0]);
+        assert_eq!(schedule.supply_at(u64::MAX), 21_000_000);
+    }
+
+    #[test]
+    fn subsidy_halves_at_era_boundaries() {
+        let schedule = EmissionSchedule::converging_to(21_000_000, 210_000, TailEmission::None);
+        assert_eq!(schedule.subsidy_at(0), 0);
+        assert_eq!((schedule.subsidy_at(210_000), schedule.subsidy_at(210_001)), (51, 26));
+        assert_eq!((schedule.subsidy_at(630_000), schedule.subsidy_at(630_001)), (13, 6));
+        assert_eq!((schedule.supply_at(210_000), schedule.supply_at(420_000)), (10_710_000, 16_170_000));
+
+        // The last era ends exactly on the cap
+        assert_eq!((schedule.subsidy_at(1_260_000), schedule.subsidy_at(1_260_001)), (1, 0));
+        assert_eq!(schedule.supply_at(1_260_000), 21_000_000);
+    }
+
+    #[test]
+    fn supply_stops_at_the_cap() {
+        // A tail of 1 pays the 630,000 coins the halvings leave until the cap is reached
+        let schedule = EmissionSchedule::converging_to(21_000_000, 210_000, TailEmission::Floor(1));
+        assert_eq!((schedule.subsidy_at(1_260_001), schedule.subsidy_at(1_890_000), schedule.subsidy_at(1_890_001)), (1, 1, 0));
+        assert_eq!((schedule.supply_at(1_890_000), schedule.supply_at(u64::MAX)), (21_000_000, 21_000_000));
+
+        // The block reaching the cap is only paid what's left
+        let schedule = EmissionSchedule::new(10, 2, TailEmission::Floor(3), 23);
+        let subsidies: Vec<u64> = (1..=4).map(|height| schedule.subsidy_at(height)).collect();
+        assert_eq!(subsidies, [10, 10, 3, 0]);
+    }
+}
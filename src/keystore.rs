@@ -0,0 +1,298 @@
+//! Encrypted keystore files for wallet keys.
+//!
+//! The secret key is encrypted with ChaCha20-Poly1305 under a key derived from a passphrase with
+//! Argon2id, a memory-hard KDF, so a stolen file can't be brute forced cheaply. The file is JSON with
+//! a version and a key type, and both are authenticated with the KDF parameters, so tampering with
+//! any of them makes decryption fail instead of silently changing how the key is read.
+use crate::codec::Encode;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+/// Version of the keystore format written by this code
+pub const KEYSTORE_VERSION: u32 = 1;
+
+/// Memory used to derive a key, in KiB (19 MiB)
+const KDF_MEMORY_COST: u32 = 19 * 1024;
+
+/// Passes over that memory
+const KDF_TIME_COST: u32 = 2;
+
+/// Lanes computed in parallel
+const KDF_PARALLELISM: u32 = 1;
+
+/// Most memory a keystore may ask for, in KiB (1 GiB)
+///
+/// The KDF parameters are only authenticated once the key is derived, so they're capped before that
+const MAX_KDF_MEMORY_COST: u32 = 1024 * 1024;
+
+/// Most passes a keystore may ask for
+const MAX_KDF_TIME_COST: u32 = 16;
+
+/// Most lanes a keystore may ask for
+const MAX_KDF_PARALLELISM: u32 = 16;
+
+/// Length of the random KDF salt
+const SALT_LENGTH: usize = 16;
+
+/// Length of the random cipher nonce
+const NONCE_LENGTH: usize = 12;
+
+/// Errors found while saving or loading a keystore
+#[derive(Debug)]
+pub enum KeystoreError {
+    /// The file couldn't be read or written
+    Io(io::Error),
+    /// The file isn't a valid keystore
+    Format(String),
+    /// The file was written by a newer format version
+    UnsupportedVersion(u32),
+    /// The file holds a kind of key this wallet can't use
+    UnsupportedKeyType(String),
+    /// Wrong passphrase, or the file was tampered with
+    Decryption,
+    /// The key derivation parameters are unusable
+    Kdf(String),
+}
+
+impl fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeystoreError::Io(error) => write!(f, "keystore I/O error: {}", error),
+            KeystoreError::Format(reason) => write!(f, "invalid keystore file: {}", reason),
+            KeystoreError::UnsupportedVersion(version) => write!(f, "unsupported keystore version {}", version),
+            KeystoreError::UnsupportedKeyType(key_type) => write!(f, "unsupported key type {}", key_type),
+            KeystoreError::Decryption => write!(f, "wrong passphrase or corrupted keystore"),
+            KeystoreError::Kdf(reason) => write!(f, "invalid key derivation parameters: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for KeystoreError {}
+
+impl From<io::Error> for KeystoreError {
+    fn from(error: io::Error) -> Self {
+        KeystoreError::Io(error)
+    }
+}
+
+/// Argon2id parameters the encryption key was derived with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    /// Base64 encoded random salt
+    pub salt: String,
+    /// Memory in KiB
+    pub memory_cost: u32,
+    /// Number of passes
+    pub time_cost: u32,
+    /// Number of lanes
+    pub parallelism: u32,
+}
+
+/// Struct representing a keystore file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Keystore {
+    /// Format version, see KEYSTORE_VERSION
+    pub version: u32,
+    /// Signature scheme of the secret key inside, e.g. "ed25519"
+    pub key_type: String,
+    /// How the encryption key is derived from the passphrase
+    pub kdf: KdfParams,
+    /// Base64 encoded ChaCha20-Poly1305 nonce
+    pub nonce: String,
+    /// Base64 encoded encrypted key, with its authentication tag
+    pub ciphertext: String,
+}
+
+impl Keystore {
+    /// Constructor encrypting a secret key with a passphrase
+    pub fn encrypt(key_type: &str, secret: &[u8], passphrase: &str) -> Result<Self, KeystoreError> {
+        let mut salt = [0u8; SALT_LENGTH];
+        let mut nonce = [0u8; NONCE_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let mut keystore = Keystore {
+            version: KEYSTORE_VERSION,
+            key_type: key_type.to_string(),
+            kdf: KdfParams {
+                salt: base64::encode(salt),
+                memory_cost: KDF_MEMORY_COST,
+                time_cost: KDF_TIME_COST,
+                parallelism: KDF_PARALLELISM,
+            },
+            nonce: base64::encode(nonce),
+            ciphertext: String::new(),
+        };
+
+        let cipher = keystore.cipher(passphrase)?;
+        let aad = keystore.associated_data();
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: secret, aad: &aad })
+            .map_err(|_| KeystoreError::Format("secret key is too large to encrypt".to_string()))?;
+        keystore.ciphertext = base64::encode(ciphertext);
+        Ok(keystore)
+    }
+
+    /// Method to decrypt the secret key; the caller reads it according to `key_type`
+    ///
+    /// The returned bytes are wiped from memory when dropped
+    pub fn decrypt(&self, passphrase: &str) -> Result<Zeroizing<Vec<u8>>, KeystoreError> {
+        if self.version != KEYSTORE_VERSION {
+            return Err(KeystoreError::UnsupportedVersion(self.version));
+        }
+
+        let nonce = decode_field("nonce", &self.nonce)?;
+        if nonce.len() != NONCE_LENGTH {
+            return Err(KeystoreError::Format(format!("nonce must be {} bytes", NONCE_LENGTH)));
+        }
+        let ciphertext = decode_field("ciphertext", &self.ciphertext)?;
+
+        let cipher = self.cipher(passphrase)?;
+        let aad = self.associated_data();
+        cipher
+            .decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: &aad })
+            .map(Zeroizing::new)
+            .map_err(|_| KeystoreError::Decryption)
+    }
+
+    /// Method to derive the cipher from the passphrase and the KDF parameters
+    ///
+    /// Costs above the caps are refused, a crafted file could otherwise exhaust memory or CPU before anything is checked
+    fn cipher(&self, passphrase: &str) -> Result<ChaCha20Poly1305, KeystoreError> {
+        if self.kdf.memory_cost > MAX_KDF_MEMORY_COST
+            || self.kdf.time_cost > MAX_KDF_TIME_COST
+            || self.kdf.parallelism > MAX_KDF_PARALLELISM
+        {
+            return Err(KeystoreError::Kdf("costs above the supported maximum".to_string()));
+        }
+        let salt = decode_field("salt", &self.kdf.salt)?;
+        let params = Params::new(self.kdf.memory_cost, self.kdf.time_cost, self.kdf.parallelism, Some(32))
+            .map_err(|error| KeystoreError::Kdf(error.to_string()))?;
+
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, key.as_mut())
+            .map_err(|error| KeystoreError::Kdf(error.to_string()))?;
+        Ok(ChaCha20Poly1305::new(Key::from_slice(key.as_ref())))
+    }
+
+    /// Method to build the data authenticated along with the key: every field except the ciphertext
+    fn associated_data(&self) -> Vec<u8> {
+        let mut data = vec![];
+        self.version.encode_to(&mut data);
+        self.key_type.encode_to(&mut data);
+        self.kdf.salt.encode_to(&mut data);
+        self.kdf.memory_cost.encode_to(&mut data);
+        self.kdf.time_cost.encode_to(&mut data);
+        self.kdf.parallelism.encode_to(&mut data);
+        self.nonce.encode_to(&mut data);
+        data
+    }
+
+    /// Method to write the keystore to a file
+    ///
+    /// The file is written next to its destination and renamed over it, so a crash never leaves half a key
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), KeystoreError> {
+        let path = path.as_ref();
+        let json = serde_json::to_vec_pretty(self).map_err(|error| KeystoreError::Format(error.to_string()))?;
+
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600); // Only the owner may read the key
+        }
+        let mut file = options.open(&temp_path)?;
+        file.write_all(&json)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    /// Constructor reading a keystore file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, KeystoreError> {
+        let json = fs::read(path)?;
+        serde_json::from_slice(&json).map_err(|error| KeystoreError::Format(error.to_string()))
+    }
+}
+
+/// Function to decode a base64 field of the keystore
+fn decode_field(name: &str, value: &str) -> Result<Vec<u8>, KeystoreError> {
+    base64::decode(value).map_err(|_| KeystoreError::Format(format!("{} is not valid base64", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signing::SchemeId;
+    use crate::wallet::Wallet;
+
+    #[test]
+    fn saved_wallets_load_with_the_same_key() {
+        let path = std::env::temp_dir().join(format!("solara-keystore-{}.json", std::process::id()));
+        let wallet = Wallet::new(SchemeId::Secp256k1).expect("key generation");
+        wallet.save(&path, "correct horse").expect("saved");
+
+        let loaded = Wallet::load(&path, "correct horse").expect("loaded");
+        assert_eq!(loaded.private_key().to_bytes(), wallet.private_key().to_bytes());
+        assert_eq!(loaded.address(), wallet.address());
+        assert!(matches!(Wallet::load(&path, "battery staple"), Err(KeystoreError::Decryption)));
+        fs::remove_file(&path).expect("removed");
+    }
+
+    #[test]
+    fn tampered_fields_fail_to_decrypt() {
+        let keystore = Keystore::encrypt("ed25519", b"secret key", "passphrase").expect("encrypted");
+        assert_eq!(keystore.decrypt("passphrase").expect("decrypted").as_slice(), b"secret key");
+
+        // The key type and KDF parameters aren't encrypted, but they're authenticated
+        let mut key_type = keystore.clone();
+        key_type.key_type = "secp256k1".to_string();
+        let mut kdf = keystore.clone();
+        kdf.kdf.time_cost += 1;
+        let mut salt = keystore.clone();
+        salt.kdf.salt = base64::encode([0u8; SALT_LENGTH]);
+        for tampered in [key_type, kdf, salt] {
+            assert!(matches!(tampered.decrypt("passphrase"), Err(KeystoreError::Decryption)));
+        }
+    }
+
+    #[test]
+    fn kdf_costs_above_the_caps_are_refused() {
+        let keystore = Keystore {
+            version: KEYSTORE_VERSION,
+            key_type: "ed25519".to_string(),
+            kdf: KdfParams {
+                salt: base64::encode([0u8; SALT_LENGTH]),
+                memory_cost: KDF_MEMORY_COST,
+                time_cost: KDF_TIME_COST,
+                parallelism: KDF_PARALLELISM,
+            },
+            nonce: base64::encode([0u8; NONCE_LENGTH]),
+            ciphertext: base64::encode([0u8; 32]),
+        };
+
+        // Argon2 would need 4 TiB, or run for hours, if these got that far
+        let mut memory = keystore.clone();
+        memory.kdf.memory_cost = u32::MAX;
+        let mut time = keystore.clone();
+        time.kdf.time_cost = u32::MAX;
+        let mut parallelism = keystore.clone();
+        parallelism.kdf.parallelism = MAX_KDF_PARALLELISM + 1;
+        for expensive in [memory, time, parallelism] {
+            assert!(matches!(expensive.decrypt(""), Err(KeystoreError::Kdf(_))));
+        }
+    }
+}
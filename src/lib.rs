@@ -0,0 +1,49 @@
+//! SolaraChain: a small proof-of-work blockchain that can be embedded in other programs.
+//!
+//! The main entry points:
+//! - [`chain::Blockchain`] validates and connects blocks, keeps the ledger and the pending transactions,
+//!   and can persist itself to block files (see [`chain::Blockchain::open`]).
+//! - [`transaction::Transaction`] is a signed transfer between two [`address::Address`]es.
+//! - [`block::Block`] and [`block::BlockHeader`] are what gets mined, stored and sent between nodes.
+//! - [`mempool::Mempool`] holds the pending transactions, refusing conflicting ones and evicting or expiring
+//!   the least useful ones.
+//! - [`wallet::Wallet`] holds a signing key of any [`signing::SchemeId`], optionally in an encrypted keystore.
+//! - [`mining::Miner`] does the multi-threaded proof-of-work, [`mining::CancelToken`] stops it.
+//! - [`utxo`] is the unspent outputs ledger, with signed multi-input transactions.
+//!
+//! Every operation that can fail returns a typed error ([`chain::ChainError`], [`chain::BlockError`],
+//! [`mining::MiningError`], [`transaction::TransactionError`], [`utxo::TxError`], ...), nothing panics
+//! or exits on bad input. All chain data has one canonical binary encoding, see [`codec`].
+use std::time::{SystemTime, UNIX_EPOCH}; // For timestamps
+
+pub mod address;
+pub mod block;
+pub mod chain;
+pub mod codec;
+pub mod difficulty;
+pub mod emission;
+pub mod keystore;
+pub mod mempool;
+pub mod merkle;
+pub mod mining;
+pub mod signing;
+pub mod storage;
+pub mod transaction;
+pub mod utxo;
+pub mod wallet;
+
+pub use address::Address;
+pub use block::{Block, BlockHeader};
+pub use chain::Blockchain;
+pub use transaction::Transaction;
+pub use wallet::Wallet;
+
+/// Function to get the current time in milliseconds since the Unix epoch
+pub(crate) fn current_timestamp() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() // A clock set before 1970 reads as the epoch
+}
+
+/// Function to format a hash as a hexadecimal string
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -1,369 +1,30 @@
-// Import necessary crates for hashing (SHA-256), random number generation, and RSA encryption
-extern crate sha2;
-extern crate rand;
-
-use rand::rngs::OsRng; // For random number generation using OS's random generator
-use sha2::{Sha256, Digest}; // For SHA-256 hashing
-use rsa::{PaddingScheme, PublicKey, PublicKeyParts, RsaPrivateKey, RsaPublicKey}; // For RSA keys and padding schemes
-use std::collections::HashMap; // For storing balances using a hash map
-use std::time::{SystemTime, UNIX_EPOCH}; // For timestamps
+//! Demo node: builds a chain, moves coins between two wallets and mines them into a block.
+//!
+//! Everything it uses comes from the solara_chain library, see src/lib.rs.
+use std::path::Path; // For the block store directory
+use std::time::Duration; // For mining timeouts
+use solara_chain::address::Address; // For checksummed addresses derived from public keys
+use solara_chain::block::Block; // For checking the encoding of the latest block
+use solara_chain::chain::{Blockchain, ConnectOutcome, Credit, TOTAL_SUPPLY}; // For the chain, its ledger and fork choice
+use solara_chain::codec::{Decode, Encode}; // For the canonical binary encoding
+use solara_chain::difficulty::{RetargetParams, Target}; // For proof-of-work targets and adjusting them
+use solara_chain::emission::{EmissionSchedule, TailEmission}; // For the block subsidy schedule
+use solara_chain::mining::{CancelToken, Miner, MiningError, MiningOutcome, MiningReport}; // For mining blocks and giving up on them
+use solara_chain::signing::SchemeId; // For choosing wallet signature schemes
+use solara_chain::storage::BlockStore; // For reporting what recovery dropped from the block store
+use solara_chain::to_hex; // For printing block hashes
+use solara_chain::transaction::Transaction; // For transfers between wallets
+use solara_chain::utxo::{self, TxError, TxInput, TxOutput, UTXOPool}; // For the unspent outputs ledger and signed spends
+use solara_chain::wallet::Wallet; // For wallet keys and keystores
 // use tokio::net::{TcpListener, TcpStream};
 // use tokio::*;
-// use serde::{Serialize, Deserialize};
-
-// Struct representing a transaction between two parties
-#[derive(Debug, Clone)]
-struct Transaction { 
-    sender: String, // Base64 encoded public key of the sender
-    receiver: String, // Base64 encoded public key of the receiver
-    amount: u64, // Amount being transferred
-    signature: Vec<u8>, // Digital signature of the transaction
-}
-
-// Implementation of the Transaction struct
-impl Transaction {
-    // Constructor for creating a new transaction
-    fn new(sender: &RsaPrivateKey, receiver: String, amount: u64) -> Self {
-        // Convert sender's private key to public key and encode it
-        let sender_public_key = sender.to_public_key();
-        let sender_key_str = base64::encode(sender_public_key.n().to_bytes_be());
-        
-        // Create a string representation of the transaction data
-        let transaction_data = format!("{}{}{}", sender_key_str, receiver, amount);
-        
-        // Hash the transaction data
-        let hashed_data = Sha256::digest(transaction_data.as_bytes());
-        
-        // Create a padding scheme for signing the transaction
-        let padding = PaddingScheme::new_pkcs1v15_sign(None);
-        
-        // Sign the hashed transaction data with the sender's private key
-        let signature = sender.sign(padding, &hashed_data).unwrap();
-        
-        // Return a new Transaction object
-        Transaction {
-            sender: sender_key_str,
-            receiver,
-            amount,
-            signature,
-        }
-    }
-
-    fn id(&self) -> String {
-        let transaction_data = format!("{}{}{}", self.sender, self.receiver, self.amount);
-        let mut hasher = Sha256::new();
-        hasher.update(transaction_data);
-        format!("{:x}", hasher.finalize())
-    }
-
-    // Method to verify a transaction's signature
-    fn verify(&self, public_key: &RsaPublicKey) -> bool {
-        // Recreate the transaction data string for verification
-        let transaction_data = format!("{}{}{}", self.sender, self.receiver, self.amount);
-        
-        // Create a padding scheme for verification
-        let padding = PaddingScheme::new_pkcs1v15_sign(None);
-        
-        // Verify the signature using the public key
-        public_key.verify(
-            padding,
-            &Sha256::digest(transaction_data.as_bytes()),
-            &self.signature,
-        ).is_ok() // Returns true if the verification is successful
-    }
-}
-
-// Struct representing a block in the blockchain
-#[derive(Debug)]
-struct Block {
-    index: u64, // Position of the block in the chain
-    timestamp: u128, // Time the block was created
-    previous_hash: String, // Hash of the previous block
-    hash: String, // Current block's hash
-    nonce: u64, // Number used for mining (proof of work)
-    transactions: Vec<Transaction>, // Transactions included in the block
-}
-
-// Implementation of the Block struct
-impl Block {
-    // Constructor for creating a new block
-    fn new(index: u64, previous_hash: String, transactions: Vec<Transaction>, difficulty: usize) -> Block {
-        // Get current timestamp
-        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
-        
-        // Initialize an empty hash and nonce
-        let hash = String::new();
-        let nonce = 0;
-        
-        // Create the block and calculate its hash
-        let mut block = Block { 
-            index,
-            timestamp,
-            previous_hash,
-            hash,
-            nonce,
-            transactions,
-        };
-        block.hash = block.calculate_hash();
-        block.mine_block(difficulty); // Mine the block to find a valid hash
-        block
-    }
-
-    // Method to calculate the hash of the block
-    fn calculate_hash(&self) -> String {
-        // Create a string representation of the block's properties
-        let input = format!("{}{}{}{}", self.index, self.timestamp, self.previous_hash, self.nonce);
-        
-        // Create a SHA-256 hasher and update it with the input
-        let mut hasher = Sha256::new();
-        hasher.update(input);
-        
-        // Return the calculated hash as a hexadecimal string
-        format!("{:x}", hasher.finalize())
-    }
-
-    // Method to mine the block (find a valid hash based on difficulty)
-    fn mine_block(&mut self, difficulty: usize) {
-        // Create a target hash prefix based on difficulty (e.g., "0000" for difficulty 4)
-        let target = "0".repeat(difficulty);
-        
-        // Keep incrementing nonce until a valid hash is found
-        while &self.hash[..difficulty] != target {
-            self.nonce += 1;
-            self.hash = self.calculate_hash(); // Recalculate hash with the new nonce
-        }
-        println!("Block mined! Hash: {}", self.hash); // Output mined hash
-    }
-}
-// UTXO (Unspent Transaction Output)
-// The UTXO model keeps track of unspent outputs rather than maintaining account balances directly.
-//  Each transaction consumes UTXOs as inputs and creates new UTXOs as outputs.
-#[derive(Debug, Clone)]
-struct UTXO {
-    tx_id: String, // ID of the transaction that created this UTXO
-    output_index: usize, // Output index in the transaction
-    amount: u64, // Amount of this UTXO
-    receiver: String, // Receiver of this UTXO
-}
-
-
-// Struct representing the blockchain itself
-#[derive(Debug)]
-struct Blockchain {
-    chain: Vec<Block>, // List of blocks in the blockchain
-    difficulty: usize, // Difficulty of mining
-    pending_transactions: Vec<Transaction>, // Transactions waiting to be added to a block
-    mining_reward: u64, // Reward given to miners
-    balances: HashMap<String, u64>, // Track wallet balances
-    total_mined: u64, // Total coins mined
-    utxos: HashMap<String, Vec<UTXO>>, // Map of public keys to their UTXOs
-}
-
-// Define the total supply limit for the coin
-const TOTAL_SUPPLY: u64 = 21_000_000; // Total limit of coins
-
-// Implementation of the Blockchain struct
-impl Blockchain {
-    // Constructor for creating a new blockchain
-    fn new(difficulty: usize, mining_reward: u64) -> Self {
-        let mut blockchain = Blockchain {
-            chain: vec![], // Initialize with an empty chain
-            difficulty,
-            pending_transactions: vec![], // Initialize with no pending transactions
-            mining_reward,
-            balances: HashMap::new(), // Initialize with no balances
-            total_mined: 0, // Initialize total mined coins to zero
-            utxos:HashMap::new(),
-        };
-        blockchain.create_genesis_block(); // Create the first block (genesis block)
-        blockchain
-    }
-
-    // Method to create the genesis block
-    fn create_genesis_block(&mut self) {
-        // Create a block with index 0, no transactions, and a previous hash of "0"
-        let genesis_block = Block::new(0, String::from("0"), vec![], self.difficulty);
-        self.chain.push(genesis_block); // Add it to the chain
-    }
-
-    // Method to get the latest block in the chain
-    fn get_latest_block(&self) -> &Block {
-        self.chain.last().unwrap() // Return the last block
-    }
-
-    // Method to create and add a transaction to the pending transactions
-    fn create_transaction(&mut self, transaction: Transaction, sender_public_key: &RsaPublicKey) {
-        // Ensure the sender has enough balance to make the transaction
-        let sender_balance = *self.balances.get(&transaction.sender).unwrap_or(&0);
-        // let sender_utxos = self.utxos.get(&transaction.sender).unwrap();
-        // let total_amount: u64 = sender_utxos.iter().map(|utxo| utxo.amount).sum();
-        
-        // Verify the transaction signature
-        if transaction.verify(sender_public_key) {
-            if sender_balance >= transaction.amount {
-                  let tx_id = transaction.id();
-              
-                 let new_utxo = UTXO {
-                      tx_id, // Use signature as a transaction ID (you can use a better ID)
-                    output_index: self.utxos.get(&transaction.receiver).unwrap_or(&vec![]).len(),
-                    amount: transaction.amount,
-                    receiver: transaction.receiver.clone(),
-                 };
-                  // Add UTXOs to the receiver
-        self.utxos.entry(transaction.receiver.clone()).or_insert(vec![]).push(new_utxo);
-          self.pending_transactions.push(transaction); // Add to pending transactions if valid
-            } else {
-                  println!("Transaction failed: insufficient UTXO"); // Notify insufficient UTXO
-            }
-        } else {
-            println!("Transaction failed: invalid signature"); // Notify invalid signature
-        }
-    }
-
-    // Method to mine pending transactions and create a new block
-    fn mine_pending_transactions(&mut self, miner_address: String) {
-        if self.pending_transactions.is_empty() {
-            println!("No transactions to mine."); // Notify if no transactions are pending
-            return; // Exit if no transactions to mine
-        }
-
-        // Get the hash of the latest block to link the new block
-        let previous_hash = self.get_latest_block().hash.clone();
-        
-        // Create a new block with the pending transactions
-        let new_block = Block::new(
-            self.chain.len() as u64, // Block index
-            previous_hash, // Previous block hash
-            self.pending_transactions.clone(), // Clone the pending transactions
-            self.difficulty, // Difficulty level
-        );
-
-        // Display the transactions included in this block
-        println!("Block {} contains the following transactions:", new_block.index);
-        for transaction in &new_block.transactions {
-            println!("{:?}", transaction); // Print each transaction
-        }
-
-        // Add the new block to the chain
-        self.chain.push(new_block);
-         // Update balances for the transactions in the newly mined block in UTXO
-         for transaction in &self.pending_transactions {
-        // Deduct the transaction amount from the sender's balance
-        if let Some(sender_utxos) = self.utxos.get_mut(&transaction.sender) {
-            let total_amount: u64 = sender_utxos.iter().map(|utxo| utxo.amount).sum();
-            if total_amount >= transaction.amount {
-                // Process each UTXO and create new UTXOs
-                let mut amount_to_spend = transaction.amount;
-
-                // Create new UTXOs for the transaction
-                for utxo in sender_utxos.clone() {
-                    if amount_to_spend == 0 {
-                        break; // If we have spent enough, stop
-                    }
-                    // Deduct from the UTXO if it's less than or equal to what we need
-                    if utxo.amount <= amount_to_spend {
-                        amount_to_spend -= utxo.amount; // Subtract the amount from the total to spend
-                        // Remove this UTXO as it has been spent
-                        sender_utxos.retain(|u| u.tx_id != utxo.tx_id); 
-                    }
-                }
-
-                // Safely borrow the receiver's UTXOs after finishing the mutable borrow
-                let output_index = self.utxos.get(&transaction.receiver).map_or(0, |v| v.len());
-                
-                // Add the transaction amount to the receiver's UTXOs
-                self.utxos.entry(transaction.receiver.clone())
-                    .or_insert(vec![]) // Initialize if not exists
-                    .push(UTXO {
-                        tx_id: transaction.id(), // Assuming you have an id() method in Transaction
-                        output_index,
-                        amount: transaction.amount,
-                        receiver: transaction.receiver.clone(),
-                    });
-
-                // Handle miner's reward
-                let miner_utxos = self.utxos.entry(miner_address.clone()).or_insert(vec![]); // Get or initialize miner's UTXOs
-                
-                // Reward the miner with mining reward, ensuring it doesn't exceed the total supply limit
-                if self.total_mined + self.mining_reward <= TOTAL_SUPPLY {
-                    miner_utxos.push(UTXO {
-                        tx_id: format!("miner_reward_{}", self.chain.len()), // Example ID for mining reward
-                        output_index: miner_utxos.len(), // Use miner's UTXOs length
-                        amount: self.mining_reward,
-                        receiver: miner_address.clone(),
-                    });
-                    self.total_mined += self.mining_reward; // Update total coins mined
-                } else {
-                    println!("Mining reward exceeds total supply limit."); // Notify if reward exceeds limit
-                }
-            } else {
-                println!("Transaction failed: insufficient UTXO for sender"); // Notify insufficient UTXO
-            }
-        }
-    }
-        // Update balances for the transactions in the newly mined block for the normal one
-        for transaction in &self.pending_transactions {
-            // Deduct the transaction amount from the sender's balance
-            if let Some(sender_balance) = self.balances.get_mut(&transaction.sender) {
-                *sender_balance -= transaction.amount; // Update sender's balance
-            }
-
-            // Add the transaction amount to the receiver's balance
-            self.balances.entry(transaction.receiver.clone()).or_insert(0); // Initialize if not exists
-            *self.balances.get_mut(&transaction.receiver).unwrap() += transaction.amount; // Update receiver's balance
-        }
-
-        // Reward the miner with mining reward, ensuring it doesn't exceed the total supply limit
-        if self.total_mined + self.mining_reward <= TOTAL_SUPPLY {
-            self.balances.entry(miner_address.clone()).or_insert(0); // Initialize miner's balance if not exists
-            *self.balances.get_mut(&miner_address).unwrap() += self.mining_reward; // Reward the miner
-            self.total_mined += self.mining_reward; // Update total coins mined
-        } else {
-            println!("Mining reward exceeds total supply limit."); // Notify if reward exceeds limit
-        }
-
-        // Clear pending transactions after mining
-        self.pending_transactions.clear();
-    }
-
-    // Method to display the entire blockchain with transactions
-    fn display_chain(&self) {
-        for block in &self.chain {
-            println!("Block {} has the following transactions:", block.index);
-            for transaction in &block.transactions {
-                println!("{:?}", transaction); // Print each transaction in the block
-            }
-        }
-    }
-}
-
-// Struct representing a wallet for managing RSA keys
-#[derive(Debug)]
-struct Wallet {
-    private_key: RsaPrivateKey, // Private key for signing transactions
-    public_key: RsaPublicKey, // Public key for receiving transactions
-}
-
-// Implementation of the Wallet struct
-impl Wallet {
-    // Constructor for creating a new wallet
-    fn new() -> Self {
-        let mut rng = OsRng; // Create a random number generator
-        let private_key = RsaPrivateKey::new(&mut rng, 2048).unwrap(); // Generate a new RSA private key
-        let public_key = private_key.to_public_key(); // Derive the public key from the private key
-        Wallet { private_key, public_key } // Return a new Wallet instance
-    }
-
-    // Method to get the public key as a base64 encoded string
-    fn get_public_key(&self) -> String {
-        base64::encode(self.public_key.n().to_bytes_be()) // Encode and return the public key
-    }
-}
 
+#[allow(dead_code)] // Not wired up until peer-to-peer networking lands
 #[derive(Debug)]
 struct Node {
     id: String,
-    peers: Vec<String>, // List of peers
+    /// List of peers
+    peers: Vec<String>,
     blockchain: Blockchain,
 }
 // impl Node {
@@ -402,11 +63,13 @@ struct Node {
 // }
 
 
-// Main function where the program execution begins
+/// Main function where the program execution begins
 #[tokio::main]
 async fn main() {
-    let difficulty = 4; // Difficulty level for mining
-    let mining_reward = 50; // Reward for mining a new block
+    let difficulty = 4; // Difficulty level for mining the genesis block (leading zero hex digits)
+    let initial_bits = Target::from_leading_zeros(difficulty).to_compact();
+    let retarget = RetargetParams::new(10, 10_000, 4); // Retarget every 10 blocks towards 10 seconds per block
+    let halving_interval = 210_000; // Blocks between halvings of the block subsidy
     //  let mut node = Node::new("node1", 4);
     // node.peers.push("127.0.0.1:8081".to_string()); // Add peers as needed
 
@@ -417,26 +80,277 @@ async fn main() {
     
 
     // Create a new blockchain instance
-    let mut blockchain = Blockchain::new(difficulty, mining_reward);
-
-    // Create two wallets (users) for transactions
-    let wallet1 = Wallet::new();
-    let wallet2 = Wallet::new();
+    // Subsidies start at 50 coins per block and halve towards the total supply
+    let emission = EmissionSchedule::converging_to(TOTAL_SUPPLY, halving_interval, TailEmission::None);
+    for (height, supply) in emission.supply_curve(4 * halving_interval, halving_interval) {
+        println!("Supply at height {}: {} (subsidy {})", height, supply, emission.subsidy_at(height + 1));
+    }
+    println!("Supply after all halvings: {}", emission.supply_at(u64::MAX));
+    let with_tail = EmissionSchedule::converging_to(TOTAL_SUPPLY, halving_interval, TailEmission::Floor(1));
+    println!("Supply at height 10000000 with a 1 coin tail: {}", with_tail.supply_at(10_000_000));
+
+    // Keep the chain in the directory given on the command line, or only in memory without one
+    let data_dir = std::env::args().nth(1);
+    let mut blockchain = match &data_dir {
+        Some(data_dir) => match Blockchain::open(data_dir, initial_bits, emission.clone(), retarget) {
+            Ok(blockchain) => {
+                if let Some(dropped) = blockchain.store().map(BlockStore::dropped_bytes).filter(|dropped| *dropped > 0) {
+                    println!("Recovered {}: dropped {} bytes of a torn record", data_dir, dropped);
+                }
+                println!("Opened chain in {} with {} blocks", data_dir, blockchain.blocks().len());
+                blockchain
+            }
+            Err(error) => {
+                println!("Could not open the chain in {}: {}", data_dir, error);
+                return;
+            }
+        },
+        None => Blockchain::new(initial_bits, emission.clone(), retarget),
+    };
+
+    // Create two wallets (users) for transactions, each with its own signature scheme
+    // With a data directory, wallet1 is kept in an encrypted keystore there so its coins survive restarts
+    let passphrase = std::env::var("SOLARA_PASSPHRASE").unwrap_or_else(|_| "solara demo passphrase".to_string());
+    let keystore_path = data_dir.as_ref().map(|data_dir| Path::new(data_dir).join("wallet1.json"));
+    let wallet1 = match &keystore_path {
+        Some(path) if path.exists() => {
+            if let Err(error) = Wallet::load(path, "not the passphrase") {
+                println!("Loading wallet1 with a wrong passphrase fails: {}", error);
+            }
+            match Wallet::load(path, &passphrase) {
+                Ok(wallet) => {
+                    println!("Loaded wallet1 from {}", path.display());
+                    wallet
+                }
+                Err(error) => {
+                    println!("Could not load wallet1 from {}: {}", path.display(), error);
+                    return;
+                }
+            }
+        }
+        _ => match Wallet::new(SchemeId::Ed25519) {
+            Ok(wallet) => {
+                if let Some(path) = &keystore_path {
+                    match wallet.save(path, &passphrase) {
+                        Ok(()) => println!("Saved wallet1 to {}", path.display()),
+                        Err(error) => println!("Could not save wallet1 to {}: {}", path.display(), error),
+                    }
+                }
+                wallet
+            }
+            Err(error) => {
+                println!("Could not create wallet1: {}", error);
+                return;
+            }
+        },
+    };
+    let wallet2 = match Wallet::new(SchemeId::Secp256k1) {
+        Ok(wallet) => wallet,
+        Err(error) => {
+            println!("Could not create wallet2: {}", error);
+            return;
+        }
+    };
+    println!("Wallet1 signs with {}, wallet2 with {}", wallet1.private_key().scheme().name(), wallet2.private_key().scheme().name());
+
+    // Addresses are short and checksummed, so a typo is caught before a transaction is built
+    let wallet2_address = wallet2.address();
+    println!("Wallet2 address: {}", wallet2_address);
+    let mut mistyped = wallet2_address.to_string();
+    let last = if mistyped.ends_with('z') { "y" } else { "z" };
+    mistyped.replace_range(mistyped.len() - 1.., last);
+    if let Err(error) = Address::parse(&mistyped) {
+        println!("Mistyped address {} rejected: {}", mistyped, error);
+    }
 
     // Set initial balance for wallet1
-    blockchain.balances.insert(wallet1.get_public_key(), 100); // Initialize wallet1 with 100 coins
+    if let Err(error) = blockchain.fund_account(&wallet1.address(), 100) {
+        println!("Could not fund wallet1: {}", error);
+        return;
+    }
 
-    // Create and process a transaction from wallet1 to wallet2
-    let transaction = Transaction::new(&wallet1.private_key, wallet2.get_public_key(), 10);
-    blockchain.create_transaction(transaction, &wallet1.public_key); // Create the transaction
+    // Create and process a transaction from wallet1 to wallet2, replaceable in case its fee turns out too low
+    let nonce = blockchain.next_nonce(wallet1.address().as_str());
+    let transaction = match Transaction::new_replaceable(wallet1.private_key(), &wallet2_address, 10, 1, nonce) {
+        Ok(transaction) => transaction,
+        Err(error) => {
+            println!("Could not sign the transaction: {}", error);
+            return;
+        }
+    };
+    let mut tx_id = transaction.id();
+    println!("Transaction signed with {} is {} bytes", wallet1.public_key().scheme().name(), transaction.size());
+    if let Err(error) = blockchain.create_transaction(transaction.clone()) {
+        println!("Transaction failed: {}", error);
+    }
+
+    // Another transaction with the same nonce spends the same coins again
+    // The mempool only takes it as a replacement of the first, and only if it pays a higher fee
+    for (amount, fee) in [(20, 1), (10, 2)] {
+        match Transaction::new(wallet1.private_key(), &wallet2_address, amount, fee, nonce) {
+            Ok(conflicting) => {
+                let conflicting_id = conflicting.id();
+                match blockchain.create_transaction(conflicting) {
+                    Ok(replaced) => {
+                        if let Some(replaced) = replaced {
+                            println!("Transaction {} replaced by fee with {}", replaced.id(), conflicting_id);
+                        }
+                        tx_id = conflicting_id;
+                    }
+                    Err(error) => println!("Conflicting transaction rejected: {}", error),
+                }
+            }
+            Err(error) => println!("Could not sign the conflicting transaction: {}", error),
+        }
+    }
+    println!("Mempool holds {} transactions ({} bytes)", blockchain.mempool().len(), blockchain.mempool().size());
+
+    // Give up on the block if it takes more than a minute, a fresh template would be needed
+    let cancel = CancelToken::new();
+    let timeout = cancel.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        timeout.cancel();
+    });
 
     // Mine pending transactions and reward the miner (wallet1)
-    blockchain.mine_pending_transactions(wallet1.get_public_key());
+    // Mining runs on its own worker threads, block_in_place keeps the runtime's other tasks running
+    mine_and_report(&mut blockchain, &wallet1.address(), &cancel);
+
+    // Replaying the same signed transaction is rejected because its nonce is used up
+    if let Err(error) = blockchain.create_transaction(transaction) {
+        println!("Replayed transaction rejected: {}", error);
+    }
+
+    // A competing miner (wallet2) builds a longer branch next to the latest block, so the chain reorganizes to it
+    // The payment in the disconnected block goes back to the mempool and is mined again
+    if let Err(error) = tokio::task::block_in_place(|| mine_competing_branch(&mut blockchain, &wallet2_address, &emission)) {
+        println!("Mining the competing branch failed: {}", error);
+    }
+    println!("Mempool holds {} transactions after the reorganization", blockchain.mempool().len());
+    mine_and_report(&mut blockchain, &wallet1.address(), &cancel);
 
     // Check and display wallet balances after the transaction
-    println!("Wallet1 balance: {}", blockchain.balances.get(&wallet1.get_public_key()).unwrap_or(&0));
-    println!("Wallet2 balance: {}", blockchain.balances.get(&wallet2.get_public_key()).unwrap_or(&0));
+    println!("Wallet1 balance: {}", blockchain.balance_of(wallet1.address().as_str()));
+    println!("Wallet2 balance: {}", blockchain.balance_of(wallet2_address.as_str()));
+
+    // Prove the payment to wallet2 from the block header alone
+    if let Some((header, proof)) = blockchain.get_transaction_proof(&tx_id) {
+        println!("Transaction {} included in block {}: {}", tx_id, header.index, header.verify_inclusion(&proof));
+    }
+
+    // Blocks travel between processes in their canonical encoding, and decode back to the same block
+    if let Some(tip) = blockchain.get_latest_block() {
+        let encoded = tip.encode();
+        println!("Latest block encodes to {} bytes, round trip: {}", encoded.len(), Block::decode(&encoded).as_ref() == Ok(tip));
+        if let Ok(json) = serde_json::to_string(tip) {
+            println!("Latest block as JSON: {} bytes", json.len());
+        }
+    }
+
+    // Read the latest block back from the block files
+    if let (Some(_), Some(tip)) = (blockchain.store(), blockchain.get_latest_block()) {
+        match blockchain.read_block_at(tip.index) {
+            Ok(Some(block)) => println!("Block {} read back from disk: {}", tip.index, block == *tip),
+            Ok(None) => println!("Block {} is missing from disk", tip.index),
+            Err(error) => println!("Could not read block {}: {}", tip.index, error),
+        }
+    }
+
+    // Signed spends are checked for value conservation and double spends, within and across transactions
+    if let Err(error) = demo_signed_spends(&wallet2, &wallet1.address()) {
+        println!("Could not sign the UTXO transactions: {}", error);
+    }
+
+    println!("Next block target: {:#010x}", blockchain.bits_at(blockchain.blocks().len() as u64));
+    println!("Chain work: {}", blockchain.chain_work());
+
+    // Check the whole chain before trusting it
+    match blockchain.validate_chain() {
+        Ok(()) => println!("Blockchain is valid"),
+        Err(error) => println!("Blockchain is invalid: {}", error),
+    }
 
     // Display the entire blockchain with all transactions
-    blockchain.display_chain();
+    for block in blockchain.blocks() {
+        print_transactions(block);
+    }
+}
+
+/// Function to mine the pending transactions into a block paying `miner_address`, and show the result
+///
+/// Mining runs on its own worker threads, block_in_place keeps the runtime's other tasks running
+fn mine_and_report(blockchain: &mut Blockchain, miner_address: &Address, cancel: &CancelToken) {
+    match tokio::task::block_in_place(|| blockchain.mine_pending_transactions(miner_address, cancel)) {
+        Ok(report) => {
+            print_mined(&report);
+            if let Some(tip) = blockchain.get_latest_block() {
+                print_transactions(tip);
+            }
+        }
+        Err(error) => println!("Mining failed: {}", error),
+    }
+}
+
+/// Function to show the hash a mining job found and how fast it hashed
+fn print_mined(report: &MiningReport) {
+    if let MiningOutcome::Found { hash, .. } = report.outcome {
+        println!("Block mined! Hash: {} ({:.0} H/s)", to_hex(&hash), report.hash_rate());
+    }
+}
+
+/// Function to show the transactions of a block
+fn print_transactions(block: &Block) {
+    println!("Block {} has the following transactions:", block.index);
+    for transaction in &block.transactions {
+        println!("{:?}", transaction); // Print each transaction in the block
+    }
+}
+
+/// Function to mine two coinbase-only blocks on the parent of the latest block, paying `miner_address`
+///
+/// The first one only ties with the latest block and stays on a side branch, the second one has more work and wins
+fn mine_competing_branch(blockchain: &mut Blockchain, miner_address: &Address, emission: &EmissionSchedule) -> Result<(), MiningError> {
+    let mut parent = match blockchain.blocks().iter().rev().nth(1) {
+        Some(parent) => (parent.index, parent.hash),
+        None => return Err(MiningError::NothingToMine), // Only the genesis block, nothing to compete with
+    };
+    for _ in 0..2 {
+        let (parent_index, parent_hash) = parent;
+        let index = parent_index + 1;
+        let coinbase = Transaction::coinbase(miner_address, emission.subsidy_at(index), index);
+        let mut block = Block::new(index, parent_hash, vec![coinbase], blockchain.bits_after(&parent_hash));
+        print_mined(&block.mine_block(&Miner::default(), &CancelToken::new())?);
+        print_transactions(&block);
+        parent = (block.index, block.hash);
+        match blockchain.connect_block(block)? {
+            ConnectOutcome::Extended => println!("Block {} extends the chain", index),
+            ConnectOutcome::Reorganized { disconnected } => {
+                println!("Block {} reorganized the chain, disconnecting {} blocks", index, disconnected)
+            }
+            ConnectOutcome::SideBranch => println!("Block {} is kept on a side branch", index),
+        }
+    }
+    Ok(())
+}
+
+/// Function to show UTXO validation: `owner` is credited 50 coins, then tries to overspend and double spend them
+fn demo_signed_spends(owner: &Wallet, payee: &Address) -> Result<(), TxError> {
+    let mut utxo_pool = UTXOPool::new();
+    let funding = Credit { block_index: 0, sequence: 0, address: owner.address().to_string(), amount: 50 };
+    let funded = utxo_pool.credit(&funding.id(), &funding.address, funding.amount);
+    let pay = |amount| vec![TxOutput { amount, recipient: payee.to_string() }];
+    let spend = || vec![TxInput::new(funded.clone())];
+
+    let overspend = utxo::Transaction::new(owner.private_key(), spend(), pay(60))?;
+    if let Err(error) = overspend.verify(&utxo_pool) {
+        println!("Overspending transaction rejected: {}", error);
+    }
+    let first = utxo::Transaction::new(owner.private_key(), spend(), pay(45))?;
+    let second = utxo::Transaction::new(owner.private_key(), spend(), pay(40))?;
+    println!("Signed spend of 45 pays a fee of {:?}", first.verify(&utxo_pool));
+    if let Err((position, error)) = utxo_pool.apply_block(vec![first, second]) {
+        println!("Block spending the same output twice rejected at transaction {}: {}", position, error);
+    }
+    Ok(())
 }
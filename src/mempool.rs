@@ -0,0 +1,478 @@
+//! Pool of pending transactions waiting to be mined.
+//!
+//! Transactions are indexed by id and by (sender, nonce), the slot a transaction spends in the
+//! account model: two transactions with the same sender and nonce conflict, and only one of them
+//! can ever be mined. A sender's pending nonces always follow on from its confirmed nonce without
+//! gaps, so only the last pending transaction of a sender is ever evicted, and expiring one also
+//! expires the later ones that depend on it.
+//! Every transaction must pay the minimum relay fee for its size, so the mempool can't be filled for free.
+//! A pending transaction that signals replaceability can be replaced by one with the same sender and
+//! nonce (opt-in replace-by-fee). The replacement must pay at least the original fee plus the incremental
+//! relay fee for its own size, so every replacement pays for the bandwidth it uses, and must not have a
+//! lower fee rate, so miners are never worse off.
+use crate::transaction::Transaction; // For the pending transactions
+use std::cmp::Ordering; // For comparing fee rates
+use std::collections::{BTreeMap, HashMap};
+use std::fmt; // For displaying mempool errors
+use std::time::Duration; // For the expiry of pending transactions
+
+/// Encoded size the pending transactions may take up before the lowest fee rates are evicted
+pub const DEFAULT_MAX_SIZE: usize = 5_000_000;
+
+/// Time a transaction may stay pending before it's dropped
+pub const DEFAULT_EXPIRY: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+
+/// Fee a replacement must add on top of the original's, per started 1000 bytes of the replacement
+pub const INCREMENTAL_RELAY_FEE: u64 = 1;
+
+/// Fee every pending transaction must pay, per started 1000 bytes, so filling the mempool is never free
+pub const MIN_RELAY_FEE: u64 = 1;
+
+/// Reasons the mempool refuses a transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MempoolError {
+    /// The same transaction is already pending
+    AlreadyPending { tx_id: String },
+    /// Pays less than MIN_RELAY_FEE for its size
+    FeeTooLow { required: u64, actual: u64 },
+    /// Another pending transaction (tx_id) uses the same sender and nonce and isn't replaceable
+    Conflict { tx_id: String },
+    /// Pays too little to replace the pending transaction tx_id
+    InsufficientFee { tx_id: String, required: u64, actual: u64 },
+    /// Pays less per byte than the pending transaction tx_id it would replace
+    LowerFeeRate { tx_id: String },
+    /// The mempool is full and the fee rate is too low to evict anything
+    Full,
+}
+
+impl fmt::Display for MempoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MempoolError::AlreadyPending { tx_id } => write!(f, "transaction {} is already pending", tx_id),
+            MempoolError::FeeTooLow { required, actual } => write!(f, "fee {} below the minimum relay fee of {}", actual, required),
+            MempoolError::Conflict { tx_id } => write!(f, "conflicts with pending transaction {}, which isn't replaceable", tx_id),
+            MempoolError::InsufficientFee { tx_id, required, actual } => {
+                write!(f, "fee {} too low to replace pending transaction {} (at least {} required)", actual, tx_id, required)
+            }
+            MempoolError::LowerFeeRate { tx_id } => write!(f, "fee rate lower than pending transaction {}", tx_id),
+            MempoolError::Full => write!(f, "mempool full, fee rate too low"),
+        }
+    }
+}
+
+impl std::error::Error for MempoolError {}
+
+/// Struct representing a pending transaction and what the mempool knows about it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MempoolEntry {
+    /// The pending transaction
+    pub transaction: Transaction,
+    /// Encoded size in bytes, counted against the size limit
+    pub size: usize,
+    /// Time the transaction was added, in milliseconds since the Unix epoch
+    pub added_at: u128,
+}
+
+/// Struct representing the mempool
+#[derive(Debug, Clone)]
+pub struct Mempool {
+    /// Pending transactions by sender and nonce, so each sender's are in nonce order
+    entries: BTreeMap<(String, u64), MempoolEntry>,
+    /// Sender and nonce of each pending transaction id
+    ids: HashMap<String, (String, u64)>,
+    /// Encoded size of all pending transactions
+    total_size: usize,
+    /// Limit on total_size
+    max_size: usize,
+    /// Time a transaction may stay pending
+    expiry: Duration,
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Mempool::new(DEFAULT_MAX_SIZE, DEFAULT_EXPIRY)
+    }
+}
+
+impl Mempool {
+    /// Constructor for an empty mempool holding at most `max_size` bytes of transactions for at most `expiry`
+    pub fn new(max_size: usize, expiry: Duration) -> Self {
+        Mempool {
+            entries: BTreeMap::new(),
+            ids: HashMap::new(),
+            total_size: 0,
+            max_size,
+            expiry,
+        }
+    }
+
+    /// Method to get the number of pending transactions
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Method to check whether no transactions are pending
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Method to get the encoded size of all pending transactions
+    pub fn size(&self) -> usize {
+        self.total_size
+    }
+
+    /// Method to check whether a transaction is pending
+    pub fn contains(&self, tx_id: &str) -> bool {
+        self.ids.contains_key(tx_id)
+    }
+
+    /// Method to get a pending transaction by id
+    pub fn get(&self, tx_id: &str) -> Option<&MempoolEntry> {
+        self.ids.get(tx_id).and_then(|key| self.entries.get(key))
+    }
+
+    /// Method to get the pending transaction of a sender with the given nonce
+    pub fn get_by_nonce(&self, sender: &str, nonce: u64) -> Option<&MempoolEntry> {
+        self.entries.get(&(sender.to_string(), nonce))
+    }
+
+    /// Method to iterate over the pending transactions, grouped by sender and in nonce order
+    pub fn transactions(&self) -> impl Iterator<Item = &Transaction> {
+        self.entries.values().map(|entry| &entry.transaction)
+    }
+
+    /// Method to iterate over the pending transactions of one sender, in nonce order
+    pub fn transactions_of<'a>(&'a self, sender: &str) -> impl Iterator<Item = &'a Transaction> {
+        self.entries
+            .range((sender.to_string(), 0)..=(sender.to_string(), u64::MAX))
+            .map(|(_, entry)| &entry.transaction)
+    }
+
+    /// Method to get the number of pending transactions of a sender
+    pub fn pending_count(&self, sender: &str) -> u64 {
+        self.transactions_of(sender).count() as u64
+    }
+
+    /// Method to get what a sender's pending transactions cost it in total (amounts plus fees)
+    pub fn pending_spend(&self, sender: &str) -> u64 {
+        self.transactions_of(sender).map(Transaction::total_cost).fold(0, u64::saturating_add)
+    }
+
+    /// Method to add a transaction that was checked against the chain, at time `now` (milliseconds)
+    ///
+    /// It must pay at least the minimum relay fee for its size
+    ///
+    /// A pending transaction with the same sender and nonce is replaced if the replace-by-fee rules allow it
+    ///
+    /// If the size limit is exceeded, the lowest fee rate transactions nothing depends on are evicted;
+    /// if that would evict the new transaction itself, nothing changes and it's refused
+    ///
+    /// Returns the transaction that was replaced, if any
+    pub fn insert(&mut self, transaction: Transaction, now: u128) -> Result<Option<Transaction>, MempoolError> {
+        let tx_id = transaction.id();
+        if self.ids.contains_key(&tx_id) {
+            return Err(MempoolError::AlreadyPending { tx_id });
+        }
+        let size = transaction.size();
+        let required = relay_fee(MIN_RELAY_FEE, size);
+        if transaction.fee < required {
+            return Err(MempoolError::FeeTooLow { required, actual: transaction.fee });
+        }
+        let key = (transaction.sender.clone(), transaction.nonce);
+        let replaced = match self.entries.get(&key) {
+            Some(existing) => {
+                check_replacement(&existing.transaction, &transaction)?;
+                self.remove(&key)
+            }
+            None => None,
+        };
+
+        self.add(key.clone(), MempoolEntry { transaction, size, added_at: now });
+        let mut evicted = vec![];
+        while self.total_size > self.max_size {
+            let lowest = match self.lowest_fee_rate_tail(&key) {
+                Some(lowest) => lowest,
+                None => break, // Nothing left to evict
+            };
+            if lowest == key {
+                // Put back what was evicted for it, the new transaction doesn't pay enough to stay
+                self.remove(&key);
+                for (evicted_key, entry) in evicted {
+                    self.add(evicted_key, entry);
+                }
+                if let Some(original) = replaced {
+                    self.add(key, original);
+                }
+                return Err(MempoolError::Full);
+            }
+            if let Some(entry) = self.remove(&lowest) {
+                evicted.push((lowest, entry));
+            }
+        }
+        Ok(replaced.map(|entry| entry.transaction))
+    }
+
+    /// Method to remove the transactions a connected block confirms or makes invalid
+    ///
+    /// Any pending transaction using the sender and nonce of a block transaction can no longer be mined
+    pub fn remove_confirmed(&mut self, transactions: &[Transaction]) {
+        for transaction in transactions.iter().filter(|transaction| !transaction.is_coinbase()) {
+            self.remove(&(transaction.sender.clone(), transaction.nonce));
+        }
+    }
+
+    /// Method to remove every pending transaction, returned grouped by sender and in nonce order
+    pub fn drain(&mut self) -> Vec<Transaction> {
+        self.ids.clear();
+        self.total_size = 0;
+        std::mem::take(&mut self.entries).into_values().map(|entry| entry.transaction).collect()
+    }
+
+    /// Method to drop the transactions that have been pending for longer than the expiry at time `now`
+    ///
+    /// The later transactions of the same sender are dropped too, they can't be mined without them
+    ///
+    /// Returns the dropped transactions
+    pub fn expire(&mut self, now: u128) -> Vec<Transaction> {
+        let expiry = self.expiry.as_millis();
+        let stale: Vec<(String, u64)> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| now.saturating_sub(entry.added_at) > expiry)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        let mut expired = vec![];
+        for (sender, nonce) in stale {
+            expired.extend(self.remove_from(&sender, nonce));
+        }
+        expired
+    }
+
+    /// Method to remove a sender's pending transaction with `nonce` and every later one, which can't be mined without it
+    ///
+    /// Returns the removed transactions, in nonce order
+    pub fn remove_from(&mut self, sender: &str, nonce: u64) -> Vec<Transaction> {
+        let keys: Vec<(String, u64)> = self
+            .entries
+            .range((sender.to_string(), nonce)..=(sender.to_string(), u64::MAX))
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.iter().filter_map(|key| self.remove(key)).map(|entry| entry.transaction).collect()
+    }
+
+    /// Method to find the last pending transaction of a sender with the lowest fee rate
+    ///
+    /// On equal fee rates `preferred` is picked, so a newcomer doesn't evict an equal transaction
+    fn lowest_fee_rate_tail(&self, preferred: &(String, u64)) -> Option<(String, u64)> {
+        let mut entries = self.entries.iter().peekable();
+        let mut tails = vec![];
+        while let Some((key, entry)) = entries.next() {
+            // A sender's last transaction is the one not followed by another of the same sender
+            let is_tail = entries.peek().is_none_or(|(next, _)| next.0 != key.0);
+            if is_tail {
+                tails.push((key, entry));
+            }
+        }
+        tails
+            .into_iter()
+            .min_by(|(a_key, a), (b_key, b)| {
+                a.transaction
+                    .cmp_fee_rate(&b.transaction)
+                    .then_with(|| (*b_key == preferred).cmp(&(*a_key == preferred)))
+            })
+            .map(|(key, _)| key.clone())
+    }
+
+    /// Method to add an entry to both indexes
+    fn add(&mut self, key: (String, u64), entry: MempoolEntry) {
+        self.total_size += entry.size;
+        self.ids.insert(entry.transaction.id(), key.clone());
+        self.entries.insert(key, entry);
+    }
+
+    /// Method to remove an entry from both indexes
+    fn remove(&mut self, key: &(String, u64)) -> Option<MempoolEntry> {
+        let entry = self.entries.remove(key)?;
+        self.total_size -= entry.size;
+        self.ids.remove(&entry.transaction.id());
+        Some(entry)
+    }
+}
+
+/// Function to get the fee a transaction of `size` bytes pays at `rate` per started 1000 bytes
+fn relay_fee(rate: u64, size: usize) -> u64 {
+    rate.saturating_mul((size as u64).div_ceil(1000))
+}
+
+/// Function to check that `replacement` may take the place of the pending `original` (same sender and nonce)
+fn check_replacement(original: &Transaction, replacement: &Transaction) -> Result<(), MempoolError> {
+    // Only transactions that opted in can be replaced, so receivers can rely on the others
+    if !original.replaceable {
+        return Err(MempoolError::Conflict { tx_id: original.id() });
+    }
+
+    // The replacement pays for its own relay on top of what the original paid, so replacing is never free
+    let required = original.fee.saturating_add(relay_fee(INCREMENTAL_RELAY_FEE, replacement.size()));
+    if replacement.fee < required {
+        return Err(MempoolError::InsufficientFee { tx_id: original.id(), required, actual: replacement.fee });
+    }
+
+    // A bigger replacement could pay more in total but less per byte, which no miner would prefer
+    if replacement.cmp_fee_rate(original) == Ordering::Less {
+        return Err(MempoolError::LowerFeeRate { tx_id: original.id() });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signing::SchemeId;
+    use crate::wallet::Wallet;
+
+    // Function to make a sender and a receiver
+    fn wallets() -> (Wallet, Wallet) {
+        (Wallet::new(SchemeId::Ed25519).expect("key generation"), Wallet::new(SchemeId::Ed25519).expect("key generation"))
+    }
+
+    #[test]
+    fn replaces_a_replaceable_transaction_paying_enough() {
+        let (sender, receiver) = wallets();
+        let mut mempool = Mempool::default();
+        let original = Transaction::new_replaceable(sender.private_key(), &receiver.address(), 10, 1, 0).expect("signing");
+        assert_eq!(mempool.insert(original.clone(), 0), Ok(None));
+
+        let required = original.fee + relay_fee(INCREMENTAL_RELAY_FEE, original.size());
+        let replacement = Transaction::new(sender.private_key(), &receiver.address(), 10, required, 0).expect("signing");
+        assert_eq!(mempool.insert(replacement.clone(), 1), Ok(Some(original.clone())));
+        assert_eq!(mempool.len(), 1);
+        assert!(mempool.contains(&replacement.id()));
+        assert!(!mempool.contains(&original.id()));
+        assert_eq!(mempool.size(), replacement.size());
+    }
+
+    #[test]
+    fn refuses_to_replace_a_transaction_that_did_not_opt_in() {
+        let (sender, receiver) = wallets();
+        let mut mempool = Mempool::default();
+        let original = Transaction::new(sender.private_key(), &receiver.address(), 10, 1, 0).expect("signing");
+        mempool.insert(original.clone(), 0).expect("accepted");
+
+        let replacement = Transaction::new(sender.private_key(), &receiver.address(), 10, 100, 0).expect("signing");
+        assert_eq!(mempool.insert(replacement, 1), Err(MempoolError::Conflict { tx_id: original.id() }));
+        assert!(mempool.contains(&original.id()));
+    }
+
+    #[test]
+    fn refuses_a_replacement_without_the_incremental_fee() {
+        let (sender, receiver) = wallets();
+        let mut mempool = Mempool::default();
+        let original = Transaction::new_replaceable(sender.private_key(), &receiver.address(), 10, 1, 0).expect("signing");
+        mempool.insert(original.clone(), 0).expect("accepted");
+
+        // A higher amount at the same fee doesn't pay for relaying the replacement
+        let replacement = Transaction::new(sender.private_key(), &receiver.address(), 20, 1, 0).expect("signing");
+        assert_eq!(
+            mempool.insert(replacement, 1),
+            Err(MempoolError::InsufficientFee { tx_id: original.id(), required: 2, actual: 1 })
+        );
+        assert!(mempool.contains(&original.id()));
+    }
+
+    #[test]
+    fn refuses_a_replacement_with_a_lower_fee_rate() {
+        let (sender, receiver) = wallets();
+        let mut mempool = Mempool::default();
+        let original = Transaction::new_replaceable(sender.private_key(), &receiver.address(), 10, 1, 0).expect("signing");
+        mempool.insert(original.clone(), 0).expect("accepted");
+
+        // The mempool doesn't check signatures, so padding one makes a big replacement that pays more in total
+        let mut replacement = Transaction::new(sender.private_key(), &receiver.address(), 10, 0, 0).expect("signing");
+        replacement.signature = vec![0; 5000];
+        replacement.fee = original.fee + relay_fee(INCREMENTAL_RELAY_FEE, replacement.size());
+        assert_eq!(mempool.insert(replacement, 1), Err(MempoolError::LowerFeeRate { tx_id: original.id() }));
+        assert!(mempool.contains(&original.id()));
+    }
+
+    #[test]
+    fn a_full_mempool_evicts_the_lowest_fee_rate_tail() {
+        let (first, receiver) = wallets();
+        let (second, third) = wallets();
+        let pay = |sender: &Wallet, fee, nonce| Transaction::new(sender.private_key(), &receiver.address(), 10, fee, nonce).expect("signing");
+
+        // The first sender's cheap transaction can't go while its pricier successor depends on it
+        let (cheap, dependent, middle, newcomer) = (pay(&first, 2, 0), pay(&first, 20, 1), pay(&second, 5, 0), pay(&third, 8, 0));
+        let room = cheap.size() + dependent.size() + middle.size() + newcomer.size() - 1;
+        let mut mempool = Mempool::new(room, DEFAULT_EXPIRY);
+        for transaction in [&cheap, &dependent, &middle, &newcomer] {
+            assert_eq!(mempool.insert(transaction.clone(), 0), Ok(None));
+        }
+        assert_eq!(mempool.len(), 3);
+        assert!(!mempool.contains(&middle.id()));
+        assert!([&cheap, &dependent, &newcomer].iter().all(|transaction| mempool.contains(&transaction.id())));
+    }
+
+    #[test]
+    fn a_newcomer_that_would_evict_itself_is_refused() {
+        let (first, receiver) = wallets();
+        let (second, third) = wallets();
+        let cheapest = Transaction::new(first.private_key(), &receiver.address(), 10, 1, 0).expect("signing");
+        let priciest = Transaction::new(second.private_key(), &receiver.address(), 10, 100, 0).expect("signing");
+        let mut mempool = Mempool::new(cheapest.size() + priciest.size(), DEFAULT_EXPIRY);
+        mempool.insert(cheapest.clone(), 0).expect("accepted");
+        mempool.insert(priciest.clone(), 0).expect("accepted");
+
+        // Padded, so evicting the cheapest isn't enough, and its fee rate is below the priciest's
+        let mut newcomer = Transaction::new(third.private_key(), &receiver.address(), 10, 10, 0).expect("signing");
+        newcomer.signature = vec![0; 500];
+        assert_eq!(mempool.insert(newcomer.clone(), 0), Err(MempoolError::Full));
+        assert!(!mempool.contains(&newcomer.id()));
+        assert!(mempool.contains(&cheapest.id()) && mempool.contains(&priciest.id()));
+        assert_eq!(mempool.size(), cheapest.size() + priciest.size());
+    }
+
+    #[test]
+    fn expiring_a_transaction_drops_the_later_ones_of_its_sender() {
+        let (sender, receiver) = wallets();
+        let (other, _) = wallets();
+        let mut mempool = Mempool::new(DEFAULT_MAX_SIZE, Duration::from_secs(10));
+        let pay = |sender: &Wallet, nonce| Transaction::new(sender.private_key(), &receiver.address(), 10, 1, nonce).expect("signing");
+        let (stale, later, unrelated) = (pay(&sender, 0), pay(&sender, 1), pay(&other, 0));
+        mempool.insert(stale.clone(), 0).expect("accepted");
+        mempool.insert(later.clone(), 9_000).expect("accepted");
+        mempool.insert(unrelated.clone(), 5_000).expect("accepted");
+
+        assert_eq!(mempool.expire(10_000), vec![]);
+        assert_eq!(mempool.expire(10_001), vec![stale, later]);
+        assert_eq!(mempool.transactions().cloned().collect::<Vec<_>>(), vec![unrelated]);
+    }
+
+    #[test]
+    fn confirmed_nonces_leave_the_mempool() {
+        let (sender, receiver) = wallets();
+        let (other, _) = wallets();
+        let mut mempool = Mempool::default();
+        let pay = |sender: &Wallet, amount, nonce| Transaction::new(sender.private_key(), &receiver.address(), amount, 1, nonce).expect("signing");
+        let (first, second, unrelated) = (pay(&sender, 10, 0), pay(&sender, 10, 1), pay(&other, 10, 0));
+        for transaction in [&first, &second, &unrelated] {
+            mempool.insert(transaction.clone(), 0).expect("accepted");
+        }
+
+        // The block confirms a different transaction with the first nonce, which conflicts all the same
+        let conflicting = pay(&sender, 20, 0);
+        mempool.remove_confirmed(&[Transaction::coinbase(&receiver.address(), 50, 1), conflicting, unrelated]);
+        assert_eq!(mempool.transactions().cloned().collect::<Vec<_>>(), vec![second.clone()]);
+        assert_eq!(mempool.size(), second.size());
+        assert!(!mempool.contains(&first.id()));
+    }
+
+    #[test]
+    fn refuses_transactions_below_the_minimum_relay_fee() {
+        let (sender, receiver) = wallets();
+        let mut mempool = Mempool::default();
+        let free = Transaction::new(sender.private_key(), &receiver.address(), 10, 0, 0).expect("signing");
+        assert_eq!(mempool.insert(free, 0), Err(MempoolError::FeeTooLow { required: MIN_RELAY_FEE, actual: 0 }));
+        assert!(mempool.is_empty());
+    }
+}
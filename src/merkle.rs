@@ -0,0 +1,211 @@
+//! Merkle tree over transaction hashes (see Transaction::hash).
+//!
+//! The root is stored in the block header, so a single sibling path is enough to prove
+//! that a transaction is part of a block without downloading the rest of the block.
+//! Leaves and inner nodes are hashed from raw bytes behind different prefixes, so no inner node
+//! can be passed off as a leaf (or the other way round) to prove something that isn't in the tree.
+use crate::codec::{Decode, DecodeError, Encode, Reader};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Byte in front of the data of a leaf
+const LEAF_PREFIX: u8 = 0x00;
+
+/// Byte in front of the two children of an inner node
+const NODE_PREFIX: u8 = 0x01;
+
+/// Function to hash the data of a leaf (for a transaction, its encoding)
+pub fn leaf_hash(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+/// Function to hash two child nodes into their parent node
+fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Function to build the next level of the tree
+///
+/// An odd node at the end of a level is paired with itself
+fn next_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level
+        .chunks(2)
+        .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+        .collect()
+}
+
+/// Function to calculate the Merkle root of a list of leaf hashes (see leaf_hash)
+pub fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    // An empty block commits to the hash of no data, which has neither prefix
+    if leaves.is_empty() {
+        return Sha256::digest(b"").into();
+    }
+
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level.remove(0)
+}
+
+/// Struct representing a compact proof that a transaction is included under a Merkle root
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    /// Hash of the proven transaction (the leaf)
+    pub leaf: [u8; 32],
+    /// Position of the leaf; each bit tells which side the sibling is on
+    pub index: usize,
+    /// Sibling hashes from the leaf level up to the root
+    pub siblings: Vec<[u8; 32]>,
+}
+
+impl MerkleProof {
+    /// Constructor for building the proof of the leaf at `index`
+    pub fn new(leaves: &[[u8; 32]], index: usize) -> Option<Self> {
+        let leaf = *leaves.get(index)?;
+
+        let mut siblings = vec![];
+        let mut level = leaves.to_vec();
+        let mut position = index;
+        while level.len() > 1 {
+            // The last node of an odd level is its own sibling
+            let sibling = level.get(position ^ 1).unwrap_or(&level[position]);
+            siblings.push(*sibling);
+            level = next_level(&level);
+            position /= 2;
+        }
+
+        Some(MerkleProof { leaf, index, siblings })
+    }
+
+    /// Method to recompute the root implied by the proof
+    pub fn compute_root(&self) -> [u8; 32] {
+        let mut hash = self.leaf;
+        let mut position = self.index;
+        for sibling in &self.siblings {
+            hash = if position.is_multiple_of(2) {
+                hash_pair(&hash, sibling)
+            } else {
+                hash_pair(sibling, &hash)
+            };
+            position /= 2;
+        }
+        hash
+    }
+
+    /// Method to check the proof against a Merkle root
+    pub fn verify(&self, merkle_root: &[u8; 32]) -> bool {
+        // Every remaining bit of the index must have been consumed by the path
+        self.index.checked_shr(self.siblings.len() as u32).unwrap_or(0) == 0
+            && self.compute_root() == *merkle_root
+    }
+}
+
+impl Encode for MerkleProof {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.leaf.encode_to(out);
+        self.index.encode_to(out);
+        self.siblings.encode_to(out);
+    }
+}
+
+impl Decode for MerkleProof {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        Ok(MerkleProof {
+            leaf: <[u8; 32]>::decode_from(reader)?,
+            index: usize::decode_from(reader)?,
+            siblings: Vec::decode_from(reader)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Function to make `count` distinct leaves
+    fn leaves(count: usize) -> Vec<[u8; 32]> {
+        (0..count).map(|leaf| leaf_hash(&leaf.to_le_bytes())).collect()
+    }
+
+    #[test]
+    fn every_leaf_has_a_valid_proof() {
+        for count in 1..=9 {
+            let leaves = leaves(count);
+            let root = merkle_root(&leaves);
+            for index in 0..count {
+                let proof = MerkleProof::new(&leaves, index).expect("leaf exists");
+                assert!(proof.verify(&root), "leaf {} of {}", index, count);
+            }
+        }
+    }
+
+    #[test]
+    fn a_single_leaf_is_its_own_root() {
+        let leaves = leaves(1);
+        assert_eq!(merkle_root(&leaves), leaves[0]);
+        assert!(MerkleProof::new(&leaves, 0).expect("leaf exists").siblings.is_empty());
+    }
+
+    #[test]
+    fn no_proof_past_the_last_leaf() {
+        assert_eq!(MerkleProof::new(&leaves(3), 3), None);
+    }
+
+    #[test]
+    fn rejects_a_proof_for_another_root() {
+        let proof = MerkleProof::new(&leaves(4), 2).expect("leaf exists");
+        assert!(!proof.verify(&merkle_root(&leaves(5))));
+    }
+
+    #[test]
+    fn rejects_a_changed_leaf_or_sibling() {
+        let leaves = leaves(6);
+        let root = merkle_root(&leaves);
+        let proof = MerkleProof::new(&leaves, 4).expect("leaf exists");
+
+        let changed_leaf = MerkleProof { leaf: leaves[5], ..proof.clone() };
+        assert!(!changed_leaf.verify(&root));
+
+        let mut changed_sibling = proof.clone();
+        changed_sibling.siblings[1] = leaves[0];
+        assert!(!changed_sibling.verify(&root));
+    }
+
+    #[test]
+    fn rejects_a_wrong_index() {
+        let leaves = leaves(4);
+        let root = merkle_root(&leaves);
+        let proof = MerkleProof::new(&leaves, 1).expect("leaf exists");
+        assert!(!MerkleProof { index: 0, ..proof.clone() }.verify(&root));
+
+        // Bits above the path would name another leaf while hashing the same way
+        assert!(!MerkleProof { index: proof.index + 4, ..proof }.verify(&root));
+    }
+
+    #[test]
+    fn an_inner_node_is_not_a_leaf() {
+        // A path starting one level up leads to the root from the parent of the first two leaves
+        let leaves = leaves(4);
+        let parent = hash_pair(&leaves[0], &leaves[1]);
+        let forged = MerkleProof { leaf: parent, index: 0, siblings: vec![hash_pair(&leaves[2], &leaves[3])] };
+        assert_eq!(forged.compute_root(), merkle_root(&leaves));
+
+        // But the two children, presented as the data of a leaf, hash to something else, so no transaction proves it
+        let children = [leaves[0], leaves[1]].concat();
+        assert_ne!(leaf_hash(&children), parent);
+    }
+
+    #[test]
+    fn proofs_round_trip() {
+        let proof = MerkleProof::new(&leaves(5), 3).expect("leaf exists");
+        assert_eq!(MerkleProof::decode(&proof.encode()), Ok(proof));
+    }
+}
@@ -0,0 +1,168 @@
+//! Multi-threaded proof-of-work miner.
+//!
+//! The nonce space is split between worker threads (worker i tries i, i + threads, i + 2 * threads, ...)
+//! and every worker regularly checks whether another one found a hash or the job was cancelled.
+use crate::block::BlockHeader;
+use crate::chain::BlockError;
+use crate::difficulty::Target;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Number of nonces a worker tries between checks of the stop flags
+const CHECK_INTERVAL: u64 = 1024;
+
+/// Struct representing a handle that stops a mining job, e.g. when a competing block arrives
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Constructor for a token that hasn't been cancelled
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    /// Method to ask the miner to stop as soon as possible
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Method to check whether the job was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// How a mining job ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MiningOutcome {
+    /// A nonce whose hash meets the target
+    Found { nonce: u64, hash: [u8; 32] },
+    /// The job was cancelled before a hash was found
+    Cancelled,
+    /// Every nonce was tried, the header must change (e.g. its timestamp) before trying again
+    Exhausted,
+}
+
+/// Struct representing the result of a mining job
+#[derive(Debug, Clone)]
+pub struct MiningReport {
+    /// How the job ended
+    pub outcome: MiningOutcome,
+    /// Number of hashes computed by all workers
+    pub hashes: u64,
+    /// Time the job took
+    pub elapsed: Duration,
+}
+
+impl MiningReport {
+    /// Method to get the hash rate of the job, in hashes per second
+    pub fn hash_rate(&self) -> f64 {
+        self.hashes as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Reasons mining doesn't add a block
+#[derive(Debug)]
+pub enum MiningError {
+    /// No transactions are pending
+    NothingToMine,
+    /// The job was cancelled before a hash was found
+    Cancelled { hashes: u64 },
+    /// The mined block couldn't be connected
+    Rejected(BlockError),
+}
+
+impl fmt::Display for MiningError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MiningError::NothingToMine => write!(f, "no transactions to mine"),
+            MiningError::Cancelled { hashes } => write!(f, "mining cancelled after {} hashes", hashes),
+            MiningError::Rejected(error) => write!(f, "mined block rejected: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for MiningError {}
+
+impl From<BlockError> for MiningError {
+    fn from(error: BlockError) -> Self {
+        MiningError::Rejected(error)
+    }
+}
+
+/// Struct representing a miner with a fixed number of worker threads
+#[derive(Debug, Clone)]
+pub struct Miner {
+    /// Number of worker threads
+    threads: usize,
+}
+
+impl Default for Miner {
+    /// One worker per available core
+    fn default() -> Self {
+        Miner::new(thread::available_parallelism().map_or(1, |threads| threads.get()))
+    }
+}
+
+impl Miner {
+    /// Constructor for a miner using `threads` worker threads
+    pub fn new(threads: usize) -> Self {
+        Miner { threads: threads.max(1) }
+    }
+
+    /// Method to search for a nonce that makes the header meet its target
+    ///
+    /// Blocks the calling thread until a hash is found, the job is cancelled or the nonces run out
+    pub fn mine(&self, header: &BlockHeader, cancel: &CancelToken) -> MiningReport {
+        let target = Target::from_compact(header.bits);
+        let stride = self.threads as u64;
+        let found: Mutex<Option<(u64, [u8; 32])>> = Mutex::new(None);
+        let stop = AtomicBool::new(false); // Set by the worker that finds a hash
+        let hashes = AtomicU64::new(0);
+        let start = Instant::now();
+
+        thread::scope(|scope| {
+            for worker in 0..stride {
+                let (target, found, stop, hashes) = (&target, &found, &stop, &hashes);
+                let mut header = header.clone();
+                scope.spawn(move || {
+                    let mut next_nonce = Some(worker);
+                    let mut tried: u64 = 0;
+                    while let Some(nonce) = next_nonce {
+                        header.nonce = nonce;
+                        let hash = header.calculate_hash();
+                        tried += 1;
+                        if target.is_met_by(&hash) {
+                            found.lock().unwrap().get_or_insert((nonce, hash)); // Keep the first hash found
+                            stop.store(true, Ordering::Relaxed);
+                            break;
+                        }
+                        if tried.is_multiple_of(CHECK_INTERVAL) {
+                            hashes.fetch_add(CHECK_INTERVAL, Ordering::Relaxed);
+                            tried = 0;
+                            if stop.load(Ordering::Relaxed) || cancel.is_cancelled() {
+                                break;
+                            }
+                        }
+                        next_nonce = nonce.checked_add(stride);
+                    }
+                    hashes.fetch_add(tried, Ordering::Relaxed);
+                });
+            }
+        });
+
+        let outcome = match found.into_inner().unwrap() {
+            Some((nonce, hash)) => MiningOutcome::Found { nonce, hash },
+            None if cancel.is_cancelled() => MiningOutcome::Cancelled,
+            None => MiningOutcome::Exhausted,
+        };
+        MiningReport {
+            outcome,
+            hashes: hashes.into_inner(),
+            elapsed: start.elapsed(),
+        }
+    }
+}
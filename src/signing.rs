@@ -0,0 +1,372 @@
+//! Signature schemes for wallet keys and transactions.
+//!
+//! Each scheme implements SignatureScheme (key generation, signing, verification and key encoding);
+//! PrivateKey and PublicKey hold a key of any scheme, so every wallet can pick its own. Encoded
+//! public keys start with the scheme's id byte, so a key, and the address hashed from it, always
+//! records which scheme verifies its signatures.
+use ed25519_dalek::{Signer, Verifier};
+use rand::rngs::OsRng;
+use rsa::pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey, EncodeRsaPrivateKey, EncodeRsaPublicKey};
+use rsa::{PaddingScheme, PublicKey as _, RsaPrivateKey, RsaPublicKey};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use zeroize::Zeroizing;
+
+/// Errors found while generating keys or signing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SigningError {
+    /// The scheme couldn't generate a key, e.g. the random number generator failed
+    KeyGeneration(String),
+    /// The key couldn't sign the message
+    Signing(String),
+}
+
+impl fmt::Display for SigningError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SigningError::KeyGeneration(reason) => write!(f, "key generation failed: {}", reason),
+            SigningError::Signing(reason) => write!(f, "signing failed: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SigningError {}
+
+/// Identifies a signature scheme in encoded keys and keystore files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemeId {
+    /// RSA-2048, PKCS#1 v1.5 signatures over SHA-256
+    Rsa,
+    /// Ed25519 (RFC 8032)
+    Ed25519,
+    /// ECDSA over secp256k1 with SHA-256, low-S signatures
+    Secp256k1,
+}
+
+impl SchemeId {
+    /// Method to get the byte identifying the scheme in encoded public keys
+    pub fn byte(self) -> u8 {
+        match self {
+            SchemeId::Rsa => 0,
+            SchemeId::Ed25519 => 1,
+            SchemeId::Secp256k1 => 2,
+        }
+    }
+
+    /// Constructor from the byte of an encoded public key
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(SchemeId::Rsa),
+            1 => Some(SchemeId::Ed25519),
+            2 => Some(SchemeId::Secp256k1),
+            _ => None,
+        }
+    }
+
+    /// Method to get the name of the scheme, used as the key type of keystore files
+    pub fn name(self) -> &'static str {
+        match self {
+            SchemeId::Rsa => "rsa-pkcs1",
+            SchemeId::Ed25519 => "ed25519",
+            SchemeId::Secp256k1 => "secp256k1",
+        }
+    }
+
+    /// Constructor from the name of a scheme
+    pub fn from_name(name: &str) -> Option<Self> {
+        [SchemeId::Rsa, SchemeId::Ed25519, SchemeId::Secp256k1]
+            .into_iter()
+            .find(|scheme| scheme.name() == name)
+    }
+}
+
+/// A way of generating keys, signing messages and verifying signatures
+pub trait SignatureScheme {
+    /// Id recorded in encoded keys
+    const ID: SchemeId;
+    type SigningKey;
+    type VerifyingKey;
+
+    /// Function to generate a new random signing key
+    fn generate() -> Result<Self::SigningKey, SigningError>;
+
+    /// Function to get the verifying key of a signing key
+    fn verifying_key(signing_key: &Self::SigningKey) -> Self::VerifyingKey;
+
+    /// Function to sign a message (the scheme hashes it as needed)
+    fn sign(signing_key: &Self::SigningKey, message: &[u8]) -> Result<Vec<u8>, SigningError>;
+
+    /// Function to check a signature of a message
+    fn verify(verifying_key: &Self::VerifyingKey, message: &[u8], signature: &[u8]) -> bool;
+
+    /// Functions to convert keys to and from bytes, without the scheme id
+    fn verifying_key_to_bytes(verifying_key: &Self::VerifyingKey) -> Vec<u8>;
+    fn verifying_key_from_bytes(bytes: &[u8]) -> Option<Self::VerifyingKey>;
+    fn signing_key_to_bytes(signing_key: &Self::SigningKey) -> Zeroizing<Vec<u8>>;
+    fn signing_key_from_bytes(bytes: &[u8]) -> Option<Self::SigningKey>;
+}
+
+/// RSA-2048 with PKCS#1 v1.5 signatures, keys are stored as PKCS#1 DER
+pub struct Rsa;
+
+impl SignatureScheme for Rsa {
+    const ID: SchemeId = SchemeId::Rsa;
+    type SigningKey = RsaPrivateKey;
+    type VerifyingKey = RsaPublicKey;
+
+    fn generate() -> Result<RsaPrivateKey, SigningError> {
+        RsaPrivateKey::new(&mut OsRng, 2048).map_err(|error| SigningError::KeyGeneration(error.to_string()))
+    }
+
+    fn verifying_key(signing_key: &RsaPrivateKey) -> RsaPublicKey {
+        signing_key.to_public_key()
+    }
+
+    fn sign(signing_key: &RsaPrivateKey, message: &[u8]) -> Result<Vec<u8>, SigningError> {
+        let padding = PaddingScheme::new_pkcs1v15_sign(None);
+        signing_key
+            .sign(padding, &Sha256::digest(message))
+            .map_err(|error| SigningError::Signing(error.to_string()))
+    }
+
+    fn verify(verifying_key: &RsaPublicKey, message: &[u8], signature: &[u8]) -> bool {
+        let padding = PaddingScheme::new_pkcs1v15_sign(None);
+        verifying_key.verify(padding, &Sha256::digest(message), signature).is_ok()
+    }
+
+    fn verifying_key_to_bytes(verifying_key: &RsaPublicKey) -> Vec<u8> {
+        let der = verifying_key.to_pkcs1_der().expect("an RSA public key always has a PKCS#1 encoding");
+        der.as_ref().to_vec()
+    }
+
+    fn verifying_key_from_bytes(bytes: &[u8]) -> Option<RsaPublicKey> {
+        RsaPublicKey::from_pkcs1_der(bytes).ok()
+    }
+
+    fn signing_key_to_bytes(signing_key: &RsaPrivateKey) -> Zeroizing<Vec<u8>> {
+        let der = signing_key.to_pkcs1_der().expect("an RSA private key always has a PKCS#1 encoding");
+        Zeroizing::new(der.as_ref().to_vec())
+    }
+
+    fn signing_key_from_bytes(bytes: &[u8]) -> Option<RsaPrivateKey> {
+        RsaPrivateKey::from_pkcs1_der(bytes).ok()
+    }
+}
+
+/// Ed25519: 32 byte keys and 64 byte signatures, verified with the strict rules so they can't be malleated
+pub struct Ed25519;
+
+impl SignatureScheme for Ed25519 {
+    const ID: SchemeId = SchemeId::Ed25519;
+    type SigningKey = ed25519_dalek::SigningKey;
+    type VerifyingKey = ed25519_dalek::VerifyingKey;
+
+    fn generate() -> Result<Self::SigningKey, SigningError> {
+        Ok(ed25519_dalek::SigningKey::generate(&mut OsRng))
+    }
+
+    fn verifying_key(signing_key: &Self::SigningKey) -> Self::VerifyingKey {
+        signing_key.verifying_key()
+    }
+
+    fn sign(signing_key: &Self::SigningKey, message: &[u8]) -> Result<Vec<u8>, SigningError> {
+        Ok(signing_key.sign(message).to_bytes().to_vec())
+    }
+
+    fn verify(verifying_key: &Self::VerifyingKey, message: &[u8], signature: &[u8]) -> bool {
+        ed25519_dalek::Signature::from_slice(signature)
+            .is_ok_and(|signature| verifying_key.verify_strict(message, &signature).is_ok())
+    }
+
+    fn verifying_key_to_bytes(verifying_key: &Self::VerifyingKey) -> Vec<u8> {
+        verifying_key.to_bytes().to_vec()
+    }
+
+    fn verifying_key_from_bytes(bytes: &[u8]) -> Option<Self::VerifyingKey> {
+        ed25519_dalek::VerifyingKey::from_bytes(bytes.try_into().ok()?).ok()
+    }
+
+    fn signing_key_to_bytes(signing_key: &Self::SigningKey) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(signing_key.to_bytes().to_vec())
+    }
+
+    fn signing_key_from_bytes(bytes: &[u8]) -> Option<Self::SigningKey> {
+        Some(ed25519_dalek::SigningKey::from_bytes(bytes.try_into().ok()?))
+    }
+}
+
+/// ECDSA over secp256k1: 33 byte compressed public keys and 64 byte low-S signatures
+pub struct Secp256k1;
+
+impl SignatureScheme for Secp256k1 {
+    const ID: SchemeId = SchemeId::Secp256k1;
+    type SigningKey = k256::ecdsa::SigningKey;
+    type VerifyingKey = k256::ecdsa::VerifyingKey;
+
+    fn generate() -> Result<Self::SigningKey, SigningError> {
+        Ok(k256::ecdsa::SigningKey::random(&mut OsRng))
+    }
+
+    fn verifying_key(signing_key: &Self::SigningKey) -> Self::VerifyingKey {
+        *signing_key.verifying_key()
+    }
+
+    fn sign(signing_key: &Self::SigningKey, message: &[u8]) -> Result<Vec<u8>, SigningError> {
+        let signature: k256::ecdsa::Signature =
+            signing_key.try_sign(message).map_err(|error| SigningError::Signing(error.to_string()))?;
+        Ok(signature.to_bytes().to_vec())
+    }
+
+    fn verify(verifying_key: &Self::VerifyingKey, message: &[u8], signature: &[u8]) -> bool {
+        // Verification rejects high-S signatures, so each signature has one valid form
+        k256::ecdsa::Signature::from_slice(signature)
+            .is_ok_and(|signature| verifying_key.verify(message, &signature).is_ok())
+    }
+
+    fn verifying_key_to_bytes(verifying_key: &Self::VerifyingKey) -> Vec<u8> {
+        verifying_key.to_encoded_point(true).as_bytes().to_vec()
+    }
+
+    fn verifying_key_from_bytes(bytes: &[u8]) -> Option<Self::VerifyingKey> {
+        // Only the compressed form is accepted, so every key has a single encoding
+        if bytes.len() != 33 {
+            return None;
+        }
+        k256::ecdsa::VerifyingKey::from_sec1_bytes(bytes).ok()
+    }
+
+    fn signing_key_to_bytes(signing_key: &Self::SigningKey) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(signing_key.to_bytes().to_vec())
+    }
+
+    fn signing_key_from_bytes(bytes: &[u8]) -> Option<Self::SigningKey> {
+        k256::ecdsa::SigningKey::from_slice(bytes).ok()
+    }
+}
+
+/// A private key of any supported scheme
+#[derive(Debug, Clone)]
+pub enum PrivateKey {
+    Rsa(RsaPrivateKey),
+    Ed25519(ed25519_dalek::SigningKey),
+    Secp256k1(k256::ecdsa::SigningKey),
+}
+
+impl PrivateKey {
+    /// Constructor for a new random key of the given scheme
+    pub fn generate(scheme: SchemeId) -> Result<Self, SigningError> {
+        match scheme {
+            SchemeId::Rsa => Rsa::generate().map(PrivateKey::Rsa),
+            SchemeId::Ed25519 => Ed25519::generate().map(PrivateKey::Ed25519),
+            SchemeId::Secp256k1 => Secp256k1::generate().map(PrivateKey::Secp256k1),
+        }
+    }
+
+    /// Method to get the scheme of the key
+    pub fn scheme(&self) -> SchemeId {
+        match self {
+            PrivateKey::Rsa(_) => Rsa::ID,
+            PrivateKey::Ed25519(_) => Ed25519::ID,
+            PrivateKey::Secp256k1(_) => Secp256k1::ID,
+        }
+    }
+
+    /// Method to get the matching public key
+    pub fn public_key(&self) -> PublicKey {
+        match self {
+            PrivateKey::Rsa(key) => PublicKey::Rsa(Rsa::verifying_key(key)),
+            PrivateKey::Ed25519(key) => PublicKey::Ed25519(Ed25519::verifying_key(key)),
+            PrivateKey::Secp256k1(key) => PublicKey::Secp256k1(Secp256k1::verifying_key(key)),
+        }
+    }
+
+    /// Method to sign a message
+    pub fn sign(&self, message: &[u8]) -> Result<Vec<u8>, SigningError> {
+        match self {
+            PrivateKey::Rsa(key) => Rsa::sign(key, message),
+            PrivateKey::Ed25519(key) => Ed25519::sign(key, message),
+            PrivateKey::Secp256k1(key) => Secp256k1::sign(key, message),
+        }
+    }
+
+    /// Method to get the secret key bytes, for keystore files
+    pub fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+        match self {
+            PrivateKey::Rsa(key) => Rsa::signing_key_to_bytes(key),
+            PrivateKey::Ed25519(key) => Ed25519::signing_key_to_bytes(key),
+            PrivateKey::Secp256k1(key) => Secp256k1::signing_key_to_bytes(key),
+        }
+    }
+
+    /// Constructor from secret key bytes of the given scheme
+    pub fn from_bytes(scheme: SchemeId, bytes: &[u8]) -> Option<Self> {
+        match scheme {
+            SchemeId::Rsa => Rsa::signing_key_from_bytes(bytes).map(PrivateKey::Rsa),
+            SchemeId::Ed25519 => Ed25519::signing_key_from_bytes(bytes).map(PrivateKey::Ed25519),
+            SchemeId::Secp256k1 => Secp256k1::signing_key_from_bytes(bytes).map(PrivateKey::Secp256k1),
+        }
+    }
+}
+
+/// A public key of any supported scheme
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublicKey {
+    Rsa(RsaPublicKey),
+    Ed25519(ed25519_dalek::VerifyingKey),
+    Secp256k1(k256::ecdsa::VerifyingKey),
+}
+
+impl PublicKey {
+    /// Method to get the scheme of the key
+    pub fn scheme(&self) -> SchemeId {
+        match self {
+            PublicKey::Rsa(_) => Rsa::ID,
+            PublicKey::Ed25519(_) => Ed25519::ID,
+            PublicKey::Secp256k1(_) => Secp256k1::ID,
+        }
+    }
+
+    /// Method to check a signature of a message
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        match self {
+            PublicKey::Rsa(key) => Rsa::verify(key, message, signature),
+            PublicKey::Ed25519(key) => Ed25519::verify(key, message, signature),
+            PublicKey::Secp256k1(key) => Secp256k1::verify(key, message, signature),
+        }
+    }
+
+    /// Method to get the key bytes, prefixed with the scheme id
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let key = match self {
+            PublicKey::Rsa(key) => Rsa::verifying_key_to_bytes(key),
+            PublicKey::Ed25519(key) => Ed25519::verifying_key_to_bytes(key),
+            PublicKey::Secp256k1(key) => Secp256k1::verifying_key_to_bytes(key),
+        };
+        let mut bytes = Vec::with_capacity(1 + key.len());
+        bytes.push(self.scheme().byte());
+        bytes.extend_from_slice(&key);
+        bytes
+    }
+
+    /// Constructor from bytes prefixed with the scheme id
+    ///
+    /// Returns None for an unknown scheme or bytes that aren't a valid key of that scheme
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (scheme, key) = bytes.split_first()?;
+        match SchemeId::from_byte(*scheme)? {
+            SchemeId::Rsa => Rsa::verifying_key_from_bytes(key).map(PublicKey::Rsa),
+            SchemeId::Ed25519 => Ed25519::verifying_key_from_bytes(key).map(PublicKey::Ed25519),
+            SchemeId::Secp256k1 => Secp256k1::verifying_key_from_bytes(key).map(PublicKey::Secp256k1),
+        }
+    }
+
+    /// Method to encode the key as a string: base64 of its bytes, scheme id first
+    pub fn encode(&self) -> String {
+        base64::encode(self.to_bytes())
+    }
+
+    /// Constructor from an encoded key, returns None if the string isn't a valid key
+    pub fn decode(encoded: &str) -> Option<Self> {
+        PublicKey::from_bytes(&base64::decode(encoded).ok()?)
+    }
+}
@@ -0,0 +1,370 @@
+//! Append-only on-disk block store.
+//!
+//! Blocks (and the credits applied between them) are appended as checksummed records to numbered
+//! block files, in the order they were received, so replaying the records rebuilds the chain state.
+//! Blocks of side branches are stored too, so a reopened chain makes the same fork choices.
+//! Each record is: magic (4 bytes) | kind (1 byte) | payload length (u32 LE) | payload | checksum (4 bytes),
+//! where the payload is the canonical encoding of the block or credit and the checksum is the start of
+//! SHA-256(kind | payload). A crash mid-write can only leave a torn record at the end of the last file,
+//! partly written or zero-filled (filesystems can extend a file before its data reaches the disk),
+//! which is detected and cut off when the store is opened.
+use crate::codec::{Decode, Encode};
+use crate::block::Block;
+use crate::chain::Credit;
+use crate::to_hex;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Marks the start of every record
+const RECORD_MAGIC: [u8; 4] = *b"SOLA";
+
+/// Size of the magic, kind and length fields in front of the payload
+const RECORD_HEADER_SIZE: usize = 9;
+
+/// Size of the checksum after the payload
+const CHECKSUM_SIZE: usize = 4;
+
+/// Size a block file may grow to before appends move on to the next file
+const MAX_BLOCK_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Kind byte of a block record
+const KIND_BLOCK: u8 = 0;
+
+/// Kind byte of a credit record
+const KIND_CREDIT: u8 = 1;
+
+/// A record stored in the block files
+#[derive(Debug)]
+pub enum Record {
+    /// A block received, on the active chain or a side branch
+    Block(Block),
+    /// Coins credited after the latest block
+    Credit(Credit),
+}
+
+/// Where a block's record lives on disk
+#[derive(Debug, Clone, Copy)]
+struct BlockLocation {
+    /// Number of the block file
+    file: u32,
+    /// Offset of the record in the file
+    offset: u64,
+    /// Length of the whole record
+    length: u64,
+}
+
+/// Struct representing the block files of a node and their index
+#[derive(Debug)]
+pub struct BlockStore {
+    /// Directory holding the block files
+    dir: PathBuf,
+    /// Number of the file appends go to
+    current_file: u32,
+    /// Size of that file
+    current_size: u64,
+    /// Location of every block, indexed by hash
+    by_hash: HashMap<[u8; 32], BlockLocation>,
+    /// Bytes of a torn record cut off when the store was opened
+    dropped_bytes: u64,
+}
+
+impl BlockStore {
+    /// Constructor opening (or creating) the store in `dir`
+    ///
+    /// Returns the store and every record in it, in the order they were appended
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<(Self, Vec<Record>)> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut store = BlockStore {
+            dir,
+            current_file: 0,
+            current_size: 0,
+            by_hash: HashMap::new(),
+            dropped_bytes: 0,
+        };
+        let mut records = vec![];
+
+        let mut file = 0;
+        while store.file_path(file).exists() {
+            let is_last = !store.file_path(file + 1).exists();
+            store.current_file = file;
+            store.current_size = store.scan_file(file, is_last, &mut records)?;
+            file += 1;
+        }
+        Ok((store, records))
+    }
+
+    /// Method to get the path of a block file
+    fn file_path(&self, file: u32) -> PathBuf {
+        self.dir.join(format!("blk{:05}.dat", file))
+    }
+
+    /// Method to read the records of a block file and index its blocks
+    ///
+    /// A torn tail of the last file is cut off; any other damage means the store is corrupt
+    ///
+    /// Returns the length of the valid part of the file
+    fn scan_file(&mut self, file: u32, is_last: bool, records: &mut Vec<Record>) -> io::Result<u64> {
+        let path = self.file_path(file);
+        let data = fs::read(&path)?;
+
+        let mut offset = 0;
+        while let Some((kind, payload, length)) = parse_record(&data[offset..]) {
+            let record: Record = match kind {
+                KIND_BLOCK => Record::Block(decode_payload(payload)?),
+                KIND_CREDIT => Record::Credit(decode_payload(payload)?),
+                _ => return Err(invalid_data(format!("unknown record kind {} in {}", kind, path.display()))),
+            };
+            if let Record::Block(block) = &record {
+                self.by_hash.insert(block.hash, BlockLocation { file, offset: offset as u64, length: length as u64 });
+            }
+            records.push(record);
+            offset += length;
+        }
+
+        if offset < data.len() {
+            if !is_last || !is_torn_tail(&data[offset..]) {
+                return Err(invalid_data(format!("corrupt record at offset {} of {}", offset, path.display())));
+            }
+
+            // Only the record being written when the node stopped can be incomplete, drop it
+            self.dropped_bytes = (data.len() - offset) as u64;
+            let handle = OpenOptions::new().write(true).open(&path)?;
+            handle.set_len(offset as u64)?;
+            handle.sync_all()?;
+        }
+        Ok(offset as u64)
+    }
+
+    /// Method to get the number of bytes of a torn record cut off when the store was opened, 0 if it was intact
+    pub fn dropped_bytes(&self) -> u64 {
+        self.dropped_bytes
+    }
+
+    /// Method to check whether a block is stored
+    pub fn contains(&self, hash: &[u8; 32]) -> bool {
+        self.by_hash.contains_key(hash)
+    }
+
+    /// Method to read a stored block back from disk by its hash
+    pub fn read_block(&self, hash: &[u8; 32]) -> io::Result<Option<Block>> {
+        let location = match self.by_hash.get(hash) {
+            Some(location) => *location,
+            None => return Ok(None),
+        };
+
+        let mut file = File::open(self.file_path(location.file))?;
+        file.seek(SeekFrom::Start(location.offset))?;
+        let mut data = vec![0; location.length as usize];
+        file.read_exact(&mut data)?;
+
+        match parse_record(&data) {
+            Some((KIND_BLOCK, payload, _)) => decode_payload(payload).map(Some),
+            _ => Err(invalid_data(format!("block {} is no longer readable", to_hex(hash)))),
+        }
+    }
+
+    /// Method to append a block, of the active chain or a side branch
+    pub fn append_block(&mut self, block: &Block) -> io::Result<()> {
+        let (file, offset, length) = self.append(KIND_BLOCK, &block.encode())?;
+        self.by_hash.insert(block.hash, BlockLocation { file, offset, length });
+        Ok(())
+    }
+
+    /// Method to append a credit applied after the latest block
+    pub fn append_credit(&mut self, credit: &Credit) -> io::Result<()> {
+        self.append(KIND_CREDIT, &credit.encode()).map(|_| ())
+    }
+
+    /// Method to write a record and flush it to disk before returning
+    ///
+    /// Returns the file, offset and length of the record
+    fn append(&mut self, kind: u8, payload: &[u8]) -> io::Result<(u32, u64, u64)> {
+        let record = frame_record(kind, payload)?;
+
+        // Start a new file once the current one is full
+        if self.current_size > 0 && self.current_size + record.len() as u64 > MAX_BLOCK_FILE_SIZE {
+            self.current_file += 1;
+            self.current_size = 0;
+        }
+
+        let path = self.file_path(self.current_file);
+        let created = !path.exists();
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        if let Err(error) = write_synced(&mut file, &record, created.then_some(self.dir.as_path())) {
+            // Cut off whatever part of the record made it to the file (e.g. on a full disk), so the
+            // next append lands at current_size and reopening doesn't mistake it for a torn tail
+            let _ = file.set_len(self.current_size).and_then(|()| file.sync_data());
+            return Err(error);
+        }
+
+        let offset = self.current_size;
+        self.current_size += record.len() as u64;
+        Ok((self.current_file, offset, record.len() as u64))
+    }
+}
+
+/// Function to write a record and flush it to disk, with the directory entry of a newly created file
+fn write_synced(file: &mut File, record: &[u8], new_file_dir: Option<&Path>) -> io::Result<()> {
+    file.write_all(record)?; // One write, so a crash leaves at most one torn record
+    file.sync_data()?;
+    if let Some(dir) = new_file_dir {
+        File::open(dir)?.sync_all()?; // Make the new file's directory entry durable too
+    }
+    Ok(())
+}
+
+/// Function to decode a record payload
+fn decode_payload<T: Decode>(payload: &[u8]) -> io::Result<T> {
+    T::decode(payload).map_err(|error| invalid_data(error.to_string()))
+}
+
+/// Function to compute the checksum of a record
+fn checksum(kind: u8, payload: &[u8]) -> [u8; CHECKSUM_SIZE] {
+    let mut hasher = Sha256::new();
+    hasher.update([kind]);
+    hasher.update(payload);
+    let digest = hasher.finalize();
+    [digest[0], digest[1], digest[2], digest[3]]
+}
+
+/// Function to build the bytes of a record
+fn frame_record(kind: u8, payload: &[u8]) -> io::Result<Vec<u8>> {
+    let length = u32::try_from(payload.len()).map_err(|_| invalid_data("record too large".to_string()))?;
+    let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + payload.len() + CHECKSUM_SIZE);
+    record.extend_from_slice(&RECORD_MAGIC);
+    record.push(kind);
+    record.extend_from_slice(&length.to_le_bytes());
+    record.extend_from_slice(payload);
+    record.extend_from_slice(&checksum(kind, payload));
+    Ok(record)
+}
+
+/// Function to parse the record at the start of `data`
+///
+/// Returns its kind, payload and total length, or None if the record is incomplete or damaged
+fn parse_record(data: &[u8]) -> Option<(u8, &[u8], usize)> {
+    let header = data.get(..RECORD_HEADER_SIZE)?;
+    if header[..4] != RECORD_MAGIC {
+        return None;
+    }
+    let kind = header[4];
+    let length = u32::from_le_bytes([header[5], header[6], header[7], header[8]]) as usize;
+
+    let end = RECORD_HEADER_SIZE.checked_add(length)?;
+    let payload = data.get(RECORD_HEADER_SIZE..end)?;
+    let stored_checksum = data.get(end..end + CHECKSUM_SIZE)?;
+    if stored_checksum != checksum(kind, payload) {
+        return None;
+    }
+    Some((kind, payload, end + CHECKSUM_SIZE))
+}
+
+/// Function to check whether `data`, the unreadable rest of the last file, is what a crash mid-write left behind
+///
+/// Only the record being appended can be affected, but what's on disk may be a prefix of it, zeros, or a mix:
+/// the filesystem can grow the file before the data is written. Nothing complete comes after it, though,
+/// so a valid record anywhere in the region means damage in the middle of the store
+fn is_torn_tail(data: &[u8]) -> bool {
+    (0..data.len()).all(|start| parse_record(&data[start..]).is_none())
+}
+
+/// Function to build the error returned for unreadable store contents
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An empty directory for a test's block files
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("solara-storage-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    // A store holding `count` credits, and the length of each record
+    fn store_with_credits(dir: &Path, count: u64) -> Vec<usize> {
+        let (mut store, _) = BlockStore::open(dir).expect("opened");
+        (0..count)
+            .map(|sequence| {
+                let credit = Credit { block_index: 0, sequence, address: "alice".to_string(), amount: 10 };
+                store.append_credit(&credit).expect("appended");
+                frame_record(KIND_CREDIT, &credit.encode()).expect("framed").len()
+            })
+            .collect()
+    }
+
+    // Overwrite or append bytes at the end of the first block file
+    fn damage(dir: &Path, from_end: usize, bytes: &[u8]) {
+        let path = dir.join("blk00000.dat");
+        let mut data = fs::read(&path).expect("readable");
+        let start = data.len() - from_end;
+        data.truncate(start);
+        data.extend_from_slice(bytes);
+        fs::write(&path, data).expect("writable");
+    }
+
+    fn sequences(records: &[Record]) -> Vec<u64> {
+        records
+            .iter()
+            .map(|record| match record {
+                Record::Credit(credit) => credit.sequence,
+                Record::Block(block) => panic!("unexpected block {}", block.index),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reopening_cuts_off_a_torn_record() {
+        let dir = test_dir("torn");
+        store_with_credits(&dir, 2);
+        let partial = frame_record(KIND_CREDIT, b"cut short by a crash").expect("framed");
+        damage(&dir, 0, &partial[..15]);
+
+        let (store, records) = BlockStore::open(&dir).expect("torn tail dropped");
+        assert_eq!((sequences(&records), store.dropped_bytes()), (vec![0, 1], 15));
+        let (store, records) = BlockStore::open(&dir).expect("reopened");
+        assert_eq!((sequences(&records), store.dropped_bytes()), (vec![0, 1], 0));
+        fs::remove_dir_all(&dir).expect("removed");
+    }
+
+    #[test]
+    fn reopening_cuts_off_a_zero_filled_tail() {
+        // The file grew but the record never reached the disk
+        let dir = test_dir("zero-tail");
+        store_with_credits(&dir, 2);
+        damage(&dir, 0, &[0; 64]);
+        let (store, records) = BlockStore::open(&dir).expect("zero-filled tail dropped");
+        assert_eq!((sequences(&records), store.dropped_bytes()), (vec![0, 1], 64));
+
+        // Or only the header did, and the payload reads back as zeros
+        let lengths = store_with_credits(&dir, 1);
+        damage(&dir, lengths[0] - RECORD_HEADER_SIZE, &vec![0; lengths[0] - RECORD_HEADER_SIZE]);
+        let (store, records) = BlockStore::open(&dir).expect("zeroed record dropped");
+        assert_eq!((sequences(&records), store.dropped_bytes()), (vec![0, 1], lengths[0] as u64));
+        fs::remove_dir_all(&dir).expect("removed");
+    }
+
+    #[test]
+    fn damaged_record_in_the_middle_is_corrupt() {
+        let dir = test_dir("middle");
+        let lengths = store_with_credits(&dir, 3);
+
+        // Zero the second record, which the third still follows
+        let path = dir.join("blk00000.dat");
+        let mut data = fs::read(&path).expect("readable");
+        data[lengths[0]..lengths[0] + lengths[1]].fill(0);
+        fs::write(&path, data).expect("writable");
+
+        let error = BlockStore::open(&dir).expect_err("corrupt store");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        fs::remove_dir_all(&dir).expect("removed");
+    }
+}
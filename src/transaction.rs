@@ -0,0 +1,226 @@
+//! Account-model transactions, the transfers recorded in blocks.
+//!
+//! A transaction moves coins from the address of its signer to a receiver, paying a fee to the miner;
+//! the sender's nonce orders its transactions and makes every one of them unique.
+use crate::address::{Address, AddressError}; // For checksummed addresses derived from public keys
+use crate::codec::{Decode, DecodeError, Encode, Reader}; // For the canonical binary encoding
+use crate::mempool::MempoolError; // For transactions the mempool refuses
+use crate::merkle::leaf_hash; // For the transaction hash, its Merkle leaf
+use crate::signing::{PrivateKey, PublicKey, SigningError}; // For signing with keys of any signature scheme
+use serde::{Deserialize, Serialize}; // For converting transactions to and from formats like JSON
+use sha2::{Digest, Sha256}; // For transaction ids
+use std::cmp::Ordering; // For comparing fee rates
+use std::fmt; // For displaying validation errors
+
+/// Struct representing a transaction between two parties
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transaction { 
+    /// Address of the sender, empty for a coinbase transaction
+    pub sender: String,
+    /// Encoded public key of the sender (see encode_public_key), revealed to spend from the address
+    pub sender_public_key: String,
+    /// Address of the receiver
+    pub receiver: String,
+    /// Amount being transferred
+    pub amount: u64,
+    /// Fee paid to the miner that includes the transaction
+    pub fee: u64,
+    /// Sender's sequence number, each value can only be used once and in order (block index for a coinbase)
+    pub nonce: u64,
+    /// Signals that while pending it may be replaced by a transaction with the same nonce paying a higher fee
+    pub replaceable: bool,
+    /// Digital signature of the transaction
+    pub signature: Vec<u8>,
+}
+
+/// Implementation of the Transaction struct
+impl Transaction {
+    /// Constructor for creating a new transaction
+    ///
+    /// Taking a parsed Address means a mistyped receiver is caught before anything is signed
+    pub fn new(sender: &PrivateKey, receiver: &Address, amount: u64, fee: u64, nonce: u64) -> Result<Self, SigningError> {
+        Transaction::signed(sender, receiver, amount, fee, nonce, false)
+    }
+
+    /// Constructor for a transaction that opts in to replace-by-fee
+    ///
+    /// While it's pending, the sender can replace it with another transaction using the same nonce and a higher fee
+    pub fn new_replaceable(sender: &PrivateKey, receiver: &Address, amount: u64, fee: u64, nonce: u64) -> Result<Self, SigningError> {
+        Transaction::signed(sender, receiver, amount, fee, nonce, true)
+    }
+
+    /// Function to build and sign a transaction
+    fn signed(sender: &PrivateKey, receiver: &Address, amount: u64, fee: u64, nonce: u64, replaceable: bool) -> Result<Self, SigningError> {
+        // Derive the sender's address from its public key
+        let sender_public_key = sender.public_key();
+        let sender_address = Address::from_public_key(&sender_public_key);
+        
+        // Encode the signed fields of the transaction
+        let transaction_data = Transaction::signing_data(sender_address.as_str(), receiver.as_str(), amount, fee, nonce, replaceable);
+        
+        // Sign the transaction data with the sender's private key, using the key's scheme
+        let signature = sender.sign(&transaction_data)?;
+        
+        // Return a new Transaction object
+        Ok(Transaction {
+            sender: sender_address.to_string(),
+            sender_public_key: sender_public_key.encode(),
+            receiver: receiver.to_string(),
+            amount,
+            fee,
+            nonce,
+            replaceable,
+            signature,
+        })
+    }
+
+    /// Constructor for the coinbase transaction that pays a block's reward and fees to its miner
+    ///
+    /// It has no sender or signature; its nonce is the block index so every coinbase has a unique id
+    pub fn coinbase(miner_address: &Address, amount: u64, block_index: u64) -> Self {
+        Transaction {
+            sender: String::new(),
+            sender_public_key: String::new(),
+            receiver: miner_address.to_string(),
+            amount,
+            fee: 0,
+            nonce: block_index,
+            replaceable: false,
+            signature: vec![],
+        }
+    }
+
+    /// Method to check whether this is a coinbase transaction
+    pub fn is_coinbase(&self) -> bool {
+        self.sender.is_empty()
+    }
+
+    /// Function to build the data that is hashed for the id and signed by the sender
+    ///
+    /// Every field except the key and signature, in canonical encoding, so different values never give the same bytes
+    ///
+    /// The key doesn't need signing, the sender address already commits to it
+    ///
+    /// The replaceable flag is signed too, so nobody but the sender can make a transaction replaceable
+    fn signing_data(sender: &str, receiver: &str, amount: u64, fee: u64, nonce: u64, replaceable: bool) -> Vec<u8> {
+        let mut data = vec![];
+        sender.encode_to(&mut data);
+        receiver.encode_to(&mut data);
+        amount.encode_to(&mut data);
+        fee.encode_to(&mut data);
+        nonce.encode_to(&mut data);
+        replaceable.encode_to(&mut data);
+        data
+    }
+
+    pub fn id(&self) -> String {
+        let transaction_data = Transaction::signing_data(&self.sender, &self.receiver, self.amount, self.fee, self.nonce, self.replaceable);
+        let mut hasher = Sha256::new();
+        hasher.update(transaction_data);
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Method to get the hash of the whole transaction, its Merkle leaf (see merkle::leaf_hash)
+    ///
+    /// Unlike the id it covers the revealed key and signature, so a block hash commits to every byte of its transactions
+    pub fn hash(&self) -> [u8; 32] {
+        leaf_hash(&self.encode())
+    }
+
+    /// Method to get the total the sender pays (amount plus fee)
+    pub fn total_cost(&self) -> u64 {
+        self.amount.saturating_add(self.fee)
+    }
+
+    /// Method to get the size of the transaction in bytes, the block space it uses
+    pub fn size(&self) -> usize {
+        self.encode().len()
+    }
+
+    /// Method to compare fee rates (fee per byte) without floating point
+    pub fn cmp_fee_rate(&self, other: &Transaction) -> Ordering {
+        (self.fee as u128 * other.size() as u128).cmp(&(other.fee as u128 * self.size() as u128))
+    }
+
+    /// Method to get the public key revealed by the sender
+    ///
+    /// Returns None if it isn't a valid key or isn't the key the sender address was derived from
+    pub fn sender_key(&self) -> Option<PublicKey> {
+        let public_key = PublicKey::decode(&self.sender_public_key)?;
+        (Address::from_public_key(&public_key).as_str() == self.sender).then_some(public_key)
+    }
+
+    /// Method to verify a transaction's signature
+    pub fn verify(&self, public_key: &PublicKey) -> bool {
+        // Recreate the signed data for verification
+        let transaction_data = Transaction::signing_data(&self.sender, &self.receiver, self.amount, self.fee, self.nonce, self.replaceable);
+        
+        // Verify the signature with the public key's scheme
+        public_key.verify(&transaction_data, &self.signature)
+    }
+}
+
+/// Reasons Blockchain::create_transaction refuses a transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionError {
+    /// The revealed public key is invalid or isn't the key of the sender address
+    InvalidSenderKey,
+    /// The receiver isn't a valid address
+    InvalidReceiver(AddressError),
+    /// The signature doesn't verify against the sender's key
+    InvalidSignature,
+    /// A reused nonce is a replay, a later one would leave a gap
+    InvalidNonce { expected: u64, actual: u64 },
+    /// Transfers nothing, and would create an output worth nothing
+    ZeroAmount,
+    /// The balance left after pending transactions doesn't cover amount plus fee
+    InsufficientFunds { available: u64, required: u64 },
+    /// Valid on the chain, but the mempool refuses it (duplicate, conflict, full)
+    Rejected(MempoolError),
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionError::InvalidSenderKey => write!(f, "public key does not match the sender address"),
+            TransactionError::InvalidReceiver(error) => write!(f, "invalid receiver: {}", error),
+            TransactionError::InvalidSignature => write!(f, "invalid signature"),
+            TransactionError::InvalidNonce { expected, actual } => write!(f, "invalid nonce {} (expected {})", actual, expected),
+            TransactionError::ZeroAmount => write!(f, "amount must be greater than zero"),
+            TransactionError::InsufficientFunds { available, required } => {
+                write!(f, "insufficient funds: {} available, {} required", available, required)
+            }
+            TransactionError::Rejected(error) => write!(f, "rejected by the mempool: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for TransactionError {}
+
+impl Encode for Transaction {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.sender.encode_to(out);
+        self.sender_public_key.encode_to(out);
+        self.receiver.encode_to(out);
+        self.amount.encode_to(out);
+        self.fee.encode_to(out);
+        self.nonce.encode_to(out);
+        self.replaceable.encode_to(out);
+        self.signature.encode_to(out);
+    }
+}
+
+impl Decode for Transaction {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        Ok(Transaction {
+            sender: String::decode_from(reader)?,
+            sender_public_key: String::decode_from(reader)?,
+            receiver: String::decode_from(reader)?,
+            amount: u64::decode_from(reader)?,
+            fee: u64::decode_from(reader)?,
+            nonce: u64::decode_from(reader)?,
+            replaceable: bool::decode_from(reader)?,
+            signature: Vec::decode_from(reader)?,
+        })
+    }
+}
@@ -0,0 +1,853 @@
+//! UTXO Model (Unspent Transaction Output)
+//!
+//! What is UTXO?: In a UTXO-based cryptocurrency, each transaction consumes previous outputs (spending the unspent transaction outputs) and creates new outputs. These outputs are available for future transactions until they are spent.
+//! UTXO Structure: Each transaction output is associated with a value and the recipient’s public key (address). UTXOs can only be spent by the recipient by creating a new transaction.
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256}; // For hashing the signed parts of a transaction
+use std::collections::{HashMap, HashSet};
+use std::fmt; // For displaying validation errors and outpoints
+use std::str::FromStr; // For parsing outpoints
+use crate::address::Address; // For checking revealed keys against recipients
+use crate::signing::{PrivateKey, PublicKey, SigningError}; // For signing inputs and revealing public keys when spending
+use crate::codec::{Decode, DecodeError, Encode, Reader}; // For the canonical binary encoding
+use crate::to_hex; // For transaction ids
+
+/// Version of the UTXO transaction format written by this code
+pub const TX_VERSION: u32 = 1;
+
+/// Rules a UTXO transaction can break; `input` and `output` are positions within the transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxError {
+    /// Written by a newer format version
+    UnsupportedVersion(u32),
+    /// Spends nothing, new coins only come from credits
+    NoInputs,
+    /// Creates nothing, the whole value would be burnt as fee
+    NoOutputs,
+    /// Spends the same output twice
+    DuplicateInput { input: usize, outpoint: OutPoint },
+    /// Spends an output that doesn't exist or is already spent
+    MissingInput { input: usize, outpoint: OutPoint },
+    /// Spends an output an earlier transaction in the block spent
+    DoubleSpend { input: usize, outpoint: OutPoint },
+    /// The revealed public key can't be decoded
+    InvalidPublicKey { input: usize },
+    /// The revealed public key isn't the one the output's address was derived from
+    KeyMismatch { input: usize },
+    /// The signature doesn't verify against the sighash
+    InvalidSignature { input: usize },
+    /// Signing an input the transaction doesn't have
+    NoSuchInput { input: usize },
+    /// SIGHASH_SINGLE is used without an output at the input's index
+    MissingSingleOutput { input: usize },
+    /// The signer's key couldn't produce a signature
+    Signing(SigningError),
+    /// Creates an output worth nothing
+    ZeroOutput { output: usize },
+    /// Input or output values don't fit in a u64
+    ValueOverflow,
+    /// Outputs are worth more than the inputs
+    InsufficientInputs { input_value: u64, output_value: u64 },
+}
+
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxError::UnsupportedVersion(version) => write!(f, "unsupported transaction version {}", version),
+            TxError::NoInputs => write!(f, "transaction has no inputs"),
+            TxError::NoOutputs => write!(f, "transaction has no outputs"),
+            TxError::DuplicateInput { input, outpoint } => write!(f, "input {} spends {} a second time", input, outpoint),
+            TxError::MissingInput { input, outpoint } => {
+                write!(f, "input {} spends {}, which is not an unspent output", input, outpoint)
+            }
+            TxError::DoubleSpend { input, outpoint } => {
+                write!(f, "input {} spends {}, already spent earlier in the block", input, outpoint)
+            }
+            TxError::InvalidPublicKey { input } => write!(f, "input {} reveals an invalid public key", input),
+            TxError::KeyMismatch { input } => write!(f, "input {} reveals a key that does not own the output", input),
+            TxError::InvalidSignature { input } => write!(f, "input {} has an invalid signature", input),
+            TxError::NoSuchInput { input } => write!(f, "transaction has no input {}", input),
+            TxError::MissingSingleOutput { input } => {
+                write!(f, "input {} signs with SIGHASH_SINGLE but there is no output {}", input, input)
+            }
+            TxError::Signing(error) => write!(f, "{}", error),
+            TxError::ZeroOutput { output } => write!(f, "output {} has no value", output),
+            TxError::ValueOverflow => write!(f, "transaction values overflow"),
+            TxError::InsufficientInputs { input_value, output_value } => {
+                write!(f, "outputs worth {} exceed inputs worth {}", output_value, input_value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TxError {}
+
+/// Which outputs an input's signature covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SighashMode {
+    /// Every output, so none of them can be changed
+    All,
+    /// Only the output at the input's own index, the others are left to other signers
+    Single,
+}
+
+/// What an input's signature commits to: the outputs picked by the mode, and either every input or only its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SighashType {
+    pub mode: SighashMode,
+    /// Sign only this input, so others can add inputs of their own
+    pub anyone_can_pay: bool,
+}
+
+impl SighashType {
+    /// Sign every input and every output, the default for ordinary payments
+    pub const ALL: SighashType = SighashType { mode: SighashMode::All, anyone_can_pay: false };
+
+    /// Sign every input and only the matching output
+    pub const SINGLE: SighashType = SighashType { mode: SighashMode::Single, anyone_can_pay: false };
+
+    /// Method to get the same mode, signing only the input itself
+    pub fn anyone_can_pay(self) -> Self {
+        SighashType { anyone_can_pay: true, ..self }
+    }
+
+    /// Method to get the byte the type is encoded as (0x01 ALL, 0x03 SINGLE, 0x80 added for ANYONECANPAY)
+    pub fn byte(self) -> u8 {
+        let mode = match self.mode {
+            SighashMode::All => 0x01,
+            SighashMode::Single => 0x03,
+        };
+        if self.anyone_can_pay { mode | 0x80 } else { mode }
+    }
+
+    /// Constructor from an encoded byte, returns None for unknown types
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        let mode = match byte & 0x7f {
+            0x01 => SighashMode::All,
+            0x03 => SighashMode::Single,
+            _ => return None,
+        };
+        Some(SighashType { mode, anyone_can_pay: byte & 0x80 != 0 })
+    }
+}
+
+impl Encode for SighashType {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.byte().encode_to(out);
+    }
+}
+
+impl Decode for SighashType {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let byte = u8::decode_from(reader)?;
+        SighashType::from_byte(byte).ok_or(DecodeError::InvalidTag(byte))
+    }
+}
+
+/// Reference to one output of a transaction, the key of the unspent outputs
+///
+/// Written as "txid:vout", which is also how it is serialized so it can key JSON maps
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct OutPoint {
+    /// Id of the transaction that created the output
+    pub txid: String,
+    /// Position of the output in that transaction, it never changes once created
+    pub vout: usize,
+}
+
+impl OutPoint {
+    /// Constructor for a reference to output `vout` of transaction `txid`
+    pub fn new(txid: impl Into<String>, vout: usize) -> Self {
+        OutPoint { txid: txid.into(), vout }
+    }
+}
+
+impl fmt::Display for OutPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.txid, self.vout)
+    }
+}
+
+impl FromStr for OutPoint {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (txid, vout) = text.rsplit_once(':').ok_or_else(|| format!("outpoint {} has no output index", text))?;
+        let vout = vout.parse().map_err(|_| format!("outpoint {} has an invalid output index", text))?;
+        Ok(OutPoint::new(txid, vout))
+    }
+}
+
+impl TryFrom<String> for OutPoint {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+impl From<OutPoint> for String {
+    fn from(outpoint: OutPoint) -> Self {
+        outpoint.to_string()
+    }
+}
+
+impl Encode for OutPoint {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.txid.encode_to(out);
+        self.vout.encode_to(out);
+    }
+}
+
+impl Decode for OutPoint {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        Ok(OutPoint {
+            txid: String::decode_from(reader)?,
+            vout: usize::decode_from(reader)?,
+        })
+    }
+}
+
+/// Transaction Input
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxInput {
+    /// Output being spent
+    pub previous_output: OutPoint,
+    /// Encoded public key of the output's owner, revealed when spending
+    pub public_key: String,
+    /// Parts of the transaction the signature covers
+    pub sighash_type: SighashType,
+    /// Signature of the transaction's sighash, to prove ownership
+    pub signature: Vec<u8>,
+}
+
+impl TxInput {
+    /// Constructor for an unsigned input spending the given output
+    pub fn new(previous_output: OutPoint) -> Self {
+        TxInput { previous_output, public_key: String::new(), sighash_type: SighashType::ALL, signature: vec![] }
+    }
+}
+
+impl Encode for TxInput {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.previous_output.encode_to(out);
+        self.public_key.encode_to(out);
+        self.sighash_type.encode_to(out);
+        self.signature.encode_to(out);
+    }
+}
+
+impl Decode for TxInput {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        Ok(TxInput {
+            previous_output: OutPoint::decode_from(reader)?,
+            public_key: String::decode_from(reader)?,
+            sighash_type: SighashType::decode_from(reader)?,
+            signature: Vec::decode_from(reader)?,
+        })
+    }
+}
+
+/// Transaction Output (UTXO)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxOutput {
+    /// Amount of the coin
+    pub amount: u64,
+    /// Address of the recipient
+    pub recipient: String,
+}
+
+impl Encode for TxOutput {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.amount.encode_to(out);
+        self.recipient.encode_to(out);
+    }
+}
+
+impl Decode for TxOutput {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        Ok(TxOutput {
+            amount: u64::decode_from(reader)?,
+            recipient: String::decode_from(reader)?,
+        })
+    }
+}
+
+/// Transaction structure
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transaction {
+    /// Format version, see TX_VERSION
+    pub version: u32,
+    /// List of inputs (consuming previous UTXOs)
+    pub inputs: Vec<TxInput>,
+    /// List of outputs (creating new UTXOs)
+    pub outputs: Vec<TxOutput>,
+    /// Earliest block height the transaction is meant for, 0 for none (signed, not enforced yet)
+    pub lock_time: u64,
+}
+
+impl Transaction {
+    /// Function to create a new transaction and sign every input with SIGHASH_ALL
+    pub fn new(sender: &PrivateKey, inputs: Vec<TxInput>, outputs: Vec<TxOutput>) -> Result<Self, TxError> {
+        let mut transaction = Transaction::unsigned(inputs, outputs, 0);
+        for index in 0..transaction.inputs.len() {
+            transaction.sign_input(index, sender, SighashType::ALL)?;
+        }
+        Ok(transaction)
+    }
+
+    /// Function to get the transaction id: the hex SHA-256 of its canonical encoding
+    ///
+    /// Its outputs are referenced as (id, position), so the id is what inputs spending them name
+    pub fn id(&self) -> String {
+        to_hex(&Sha256::digest(self.encode()))
+    }
+
+    /// Function to create a transaction whose inputs are signed later, possibly by several owners
+    pub fn unsigned(inputs: Vec<TxInput>, outputs: Vec<TxOutput>, lock_time: u64) -> Self {
+        Transaction { version: TX_VERSION, inputs, outputs, lock_time }
+    }
+
+    /// Function to sign one input, revealing the signer's public key
+    pub fn sign_input(&mut self, index: usize, signer: &PrivateKey, sighash_type: SighashType) -> Result<(), TxError> {
+        if index >= self.inputs.len() {
+            return Err(TxError::NoSuchInput { input: index });
+        }
+        let sighash = self.sighash(index, sighash_type).ok_or(TxError::MissingSingleOutput { input: index })?;
+        let signature = signer.sign(&sighash).map_err(TxError::Signing)?;
+        let input = &mut self.inputs[index];
+        input.public_key = signer.public_key().encode();
+        input.sighash_type = sighash_type;
+        input.signature = signature;
+        Ok(())
+    }
+
+    /// Function to compute the hash an input's signature covers
+    ///
+    /// It commits to the version, lock time and sighash type, the outpoints of every input (only this
+    /// one with ANYONECANPAY), and every output (only the one at this input's index with SINGLE).
+    ///
+    /// Public keys and signatures aren't covered: the spent outputs' addresses already commit to the keys.
+    ///
+    /// Returns None if there is no such input, or SINGLE has no matching output
+    pub fn sighash(&self, index: usize, sighash_type: SighashType) -> Option<[u8; 32]> {
+        let input = self.inputs.get(index)?;
+        let mut data = vec![];
+        self.version.encode_to(&mut data);
+        self.lock_time.encode_to(&mut data);
+        sighash_type.encode_to(&mut data);
+
+        if sighash_type.anyone_can_pay {
+            crate::codec::encode_length(1, &mut data);
+            input.previous_output.encode_to(&mut data);
+        } else {
+            crate::codec::encode_length(self.inputs.len(), &mut data);
+            for input in &self.inputs {
+                input.previous_output.encode_to(&mut data);
+            }
+        }
+
+        match sighash_type.mode {
+            SighashMode::All => self.outputs.encode_to(&mut data),
+            SighashMode::Single => {
+                // The input's position is signed too, so the signature can't be moved to pair with another output
+                index.encode_to(&mut data);
+                self.outputs.get(index)?.encode_to(&mut data);
+            }
+        }
+        Some(Sha256::digest(&data).into())
+    }
+
+    /// Function to get the fee, the value of the inputs not claimed by the outputs
+    ///
+    /// Returns None if an input is unknown or the outputs are worth more than the inputs
+    pub fn fee(&self, utxo_pool: &UTXOPool) -> Option<u64> {
+        let mut input_value: u64 = 0;
+        for input in &self.inputs {
+            let prev_output = utxo_pool.get_utxo(&input.previous_output)?;
+            input_value = input_value.checked_add(prev_output.amount)?;
+        }
+        let output_value = self.outputs.iter().try_fold(0u64, |total, output| total.checked_add(output.amount))?;
+        input_value.checked_sub(output_value)
+    }
+
+    /// Function to fully validate the transaction against the unspent outputs
+    ///
+    /// Checks the version, that no output is spent twice, that every input is unspent and signed by its
+    /// owner, that every output has value, and that the outputs are worth no more than the inputs.
+    ///
+    /// Returns the fee, the value of the inputs not claimed by the outputs
+    pub fn verify(&self, utxo_pool: &UTXOPool) -> Result<u64, TxError> {
+        if self.version != TX_VERSION {
+            return Err(TxError::UnsupportedVersion(self.version));
+        }
+        if self.inputs.is_empty() {
+            return Err(TxError::NoInputs);
+        }
+        if self.outputs.is_empty() {
+            return Err(TxError::NoOutputs);
+        }
+
+        // Each output may only be spent once, checked before any signature work
+        let mut outpoints = HashSet::new();
+        for (position, input) in self.inputs.iter().enumerate() {
+            if !outpoints.insert(&input.previous_output) {
+                return Err(TxError::DuplicateInput { input: position, outpoint: input.previous_output.clone() });
+            }
+        }
+
+        let mut input_value: u64 = 0;
+        for (position, input) in self.inputs.iter().enumerate() {
+            let prev_output = utxo_pool
+                .get_utxo(&input.previous_output)
+                .ok_or_else(|| TxError::MissingInput { input: position, outpoint: input.previous_output.clone() })?;
+            input_value = input_value.checked_add(prev_output.amount).ok_or(TxError::ValueOverflow)?;
+
+            // The revealed key must be the one the recipient address was derived from
+            let recipient_public_key =
+                PublicKey::decode(&input.public_key).ok_or(TxError::InvalidPublicKey { input: position })?;
+            if Address::from_public_key(&recipient_public_key).as_str() != prev_output.recipient {
+                return Err(TxError::KeyMismatch { input: position });
+            }
+
+            // Verify that the input signature over the sighash matches the owner's public key
+            let sighash = self
+                .sighash(position, input.sighash_type)
+                .ok_or(TxError::MissingSingleOutput { input: position })?;
+            if !recipient_public_key.verify(&sighash, &input.signature) {
+                return Err(TxError::InvalidSignature { input: position });
+            }
+        }
+
+        let mut output_value: u64 = 0;
+        for (position, output) in self.outputs.iter().enumerate() {
+            if output.amount == 0 {
+                return Err(TxError::ZeroOutput { output: position });
+            }
+            output_value = output_value.checked_add(output.amount).ok_or(TxError::ValueOverflow)?;
+        }
+        input_value
+            .checked_sub(output_value)
+            .ok_or(TxError::InsufficientInputs { input_value, output_value })
+    }
+}
+
+impl Encode for Transaction {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.version.encode_to(out);
+        self.inputs.encode_to(out);
+        self.outputs.encode_to(out);
+        self.lock_time.encode_to(out);
+    }
+}
+
+impl Decode for Transaction {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        Ok(Transaction {
+            version: u32::decode_from(reader)?,
+            inputs: Vec::decode_from(reader)?,
+            outputs: Vec::decode_from(reader)?,
+            lock_time: u64::decode_from(reader)?,
+        })
+    }
+}
+
+/// Changes a block made to the UTXO pool, kept so they can be reversed when the block is disconnected
+///
+/// Recorded while the block is applied, so building it costs as much as the block, not the whole pool
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UtxoUndo {
+    /// Outputs the block spent, restored on undo
+    pub spent: Vec<(OutPoint, TxOutput)>,
+    /// Outputs the block created, removed on undo
+    pub created: Vec<OutPoint>,
+}
+
+impl UtxoUndo {
+    /// Record an output being spent
+    ///
+    /// An output created by the same changes never existed before them, so it only stops being recorded as created
+    fn record_spent(&mut self, outpoint: OutPoint, output: TxOutput) {
+        match self.created.iter().position(|created| *created == outpoint) {
+            Some(position) => {
+                self.created.remove(position);
+            }
+            None => self.spent.push((outpoint, output)),
+        }
+    }
+}
+
+/// UTXO Pool to manage unspent transaction outputs
+///
+/// This is the ledger of the chain: balances are derived from the unspent outputs, never stored separately
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UTXOPool {
+    /// Unspent outputs by outpoint, spending one leaves the others where they are
+    pool: HashMap<OutPoint, TxOutput>,
+}
+
+impl UTXOPool {
+    /// Create a new UTXO pool
+    pub fn new() -> Self {
+        UTXOPool {
+            pool: HashMap::new(),
+        }
+    }
+
+    /// Add outputs created by the transaction with the given id
+    fn add_outputs(&mut self, txid: &str, outputs: Vec<TxOutput>) {
+        for (vout, output) in outputs.into_iter().enumerate() {
+            self.pool.insert(OutPoint::new(txid, vout), output);
+        }
+    }
+
+    /// Get a UTXO by its outpoint
+    pub fn get_utxo(&self, outpoint: &OutPoint) -> Option<&TxOutput> {
+        self.pool.get(outpoint)
+    }
+
+    /// Remove UTXOs that have been spent
+    pub fn remove_spent_utxos(&mut self, inputs: &[TxInput]) {
+        for input in inputs {
+            self.pool.remove(&input.previous_output);
+        }
+    }
+
+    /// Apply a transaction to the pool: spend its inputs and add its outputs
+    ///
+    /// Returns the transaction id its outputs are recorded under
+    pub fn apply_transaction(&mut self, transaction: Transaction) -> String {
+        let txid = transaction.id();
+        self.remove_spent_utxos(&transaction.inputs);
+        self.add_outputs(&txid, transaction.outputs);
+        txid
+    }
+
+    /// Validate and apply the transactions of a block in order, each against the outputs left by the ones before
+    ///
+    /// Returns the total fees, or the position of the first invalid transaction and why; the pool is only
+    /// changed if every transaction is valid
+    pub fn apply_block(&mut self, transactions: Vec<Transaction>) -> Result<u64, (usize, TxError)> {
+        let mut pool = self.clone();
+        let mut spent = HashSet::new();
+        let mut fees: u64 = 0;
+        for (position, transaction) in transactions.into_iter().enumerate() {
+            // Name spends of outputs used up earlier in the block, rather than reporting them as missing
+            for (input, tx_input) in transaction.inputs.iter().enumerate() {
+                if spent.contains(&tx_input.previous_output) {
+                    let error = TxError::DoubleSpend { input, outpoint: tx_input.previous_output.clone() };
+                    return Err((position, error));
+                }
+            }
+
+            let fee = transaction.verify(&pool).map_err(|error| (position, error))?;
+            fees = fees.checked_add(fee).ok_or((position, TxError::ValueOverflow))?;
+            spent.extend(transaction.inputs.iter().map(|input| input.previous_output.clone()));
+            pool.apply_transaction(transaction);
+        }
+        *self = pool;
+        Ok(fees)
+    }
+
+    /// Get the unspent outputs owned by an address with their amounts
+    ///
+    /// Sorted, so coin selection is the same on every node replaying the chain
+    pub fn unspent_outputs_of(&self, owner: &str) -> Vec<(OutPoint, u64)> {
+        let mut unspent: Vec<(OutPoint, u64)> = self
+            .pool
+            .iter()
+            .filter(|(_, output)| output.recipient == owner)
+            .map(|(outpoint, output)| (outpoint.clone(), output.amount))
+            .collect();
+        unspent.sort();
+        unspent
+    }
+
+    /// Get the balance of an address (the sum of its unspent outputs)
+    ///
+    /// Saturates at u64::MAX rather than overflowing; the chain's supply cap keeps real balances far below it
+    pub fn balance_of(&self, owner: &str) -> u64 {
+        self.unspent_outputs_of(owner).iter().map(|(_, amount)| *amount).fold(0, u64::saturating_add)
+    }
+
+    /// Get the balance of every address holding unspent outputs, saturating like balance_of
+    pub fn balances(&self) -> HashMap<String, u64> {
+        let mut balances: HashMap<String, u64> = HashMap::new();
+        for output in self.pool.values() {
+            let balance = balances.entry(output.recipient.clone()).or_insert(0);
+            *balance = balance.saturating_add(output.amount);
+        }
+        balances
+    }
+
+    /// Reverse recorded changes: remove the outputs they created and restore the ones they spent
+    pub fn undo(&mut self, undo: &UtxoUndo) {
+        for outpoint in &undo.created {
+            self.pool.remove(outpoint);
+        }
+        for (outpoint, output) in &undo.spent {
+            self.pool.insert(outpoint.clone(), output.clone());
+        }
+    }
+
+    /// Credit newly issued coins to an address
+    /// `issuer_id` is the hash of whatever issued the coins (a coinbase or a credit), which keeps it unique
+    pub fn credit(&mut self, issuer_id: &str, owner: &str, amount: u64) -> OutPoint {
+        self.add_outputs(issuer_id, vec![TxOutput { amount, recipient: owner.to_string() }]);
+        OutPoint::new(issuer_id, 0)
+    }
+
+    /// Move `amount` from sender to receiver by spending the sender's outputs and returning the change
+    ///
+    /// The fee is left unclaimed by the outputs, for the miner to collect; the outputs spent and created are recorded in `undo`
+    ///
+    /// Returns the id of the ledger transaction, or None (and changes nothing) if the sender can't cover amount plus fee
+    pub fn transfer(&mut self, sender: &str, receiver: &str, amount: u64, fee: u64, undo: &mut UtxoUndo) -> Option<String> {
+        let cost = amount.checked_add(fee)?;
+
+        // Select the sender's outputs until they cover the amount
+        // Summed as a u128, which can't overflow; the change is less than the last output selected, so it fits in a u64
+        let mut inputs = vec![];
+        let mut selected: u128 = 0;
+        for (outpoint, output_amount) in self.unspent_outputs_of(sender) {
+            if selected >= cost as u128 {
+                break;
+            }
+            selected += output_amount as u128;
+            // The transfer is authorised by the account signature, so inputs carry no signature
+            inputs.push(TxInput::new(outpoint));
+        }
+        if selected < cost as u128 {
+            return None;
+        }
+
+        // Pay the receiver and return the change to the sender
+        // The id is unique without a caller-chosen name: no two transactions can spend the same outputs
+        let mut outputs = vec![TxOutput { amount, recipient: receiver.to_string() }];
+        if selected > cost as u128 {
+            outputs.push(TxOutput { amount: (selected - cost as u128) as u64, recipient: sender.to_string() });
+        }
+
+        for input in &inputs {
+            if let Some(output) = self.pool.get(&input.previous_output) {
+                undo.record_spent(input.previous_output.clone(), output.clone());
+            }
+        }
+        let created = outputs.len();
+        let txid = self.apply_transaction(Transaction::unsigned(inputs, outputs, 0));
+        undo.created.extend((0..created).map(|vout| OutPoint::new(&txid, vout)));
+        Some(txid)
+    }
+}
+
+/// The pool is encoded as its entries sorted by outpoint, so equal pools give equal bytes
+impl Encode for UTXOPool {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        let mut entries: Vec<_> = self.pool.iter().collect();
+        entries.sort_by_key(|(outpoint, _)| *outpoint);
+        crate::codec::encode_length(entries.len(), out);
+        for (outpoint, output) in entries {
+            outpoint.encode_to(out);
+            output.encode_to(out);
+        }
+    }
+}
+
+impl Decode for UTXOPool {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self, DecodeError> {
+        let count = reader.length()?;
+        let mut pool = HashMap::new();
+        let mut previous: Option<OutPoint> = None;
+        for _ in 0..count {
+            let outpoint = OutPoint::decode_from(reader)?;
+            if previous.as_ref().is_some_and(|previous| *previous >= outpoint) {
+                return Err(DecodeError::NonCanonical("UTXO pool entries must be sorted and unique"));
+            }
+            pool.insert(outpoint.clone(), TxOutput::decode_from(reader)?);
+            previous = Some(outpoint);
+        }
+        Ok(UTXOPool { pool })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signing::SchemeId;
+    use crate::wallet::Wallet;
+
+    // A pool holding two outputs of the owner, worth 30 and 20
+    fn funded(owner: &Wallet) -> (UTXOPool, OutPoint, OutPoint) {
+        let mut pool = UTXOPool::new();
+        let first = pool.credit("credit-a", owner.address().as_str(), 30);
+        let second = pool.credit("credit-b", owner.address().as_str(), 20);
+        (pool, first, second)
+    }
+
+    fn pay(amount: u64, recipient: &Wallet) -> TxOutput {
+        TxOutput { amount, recipient: recipient.address().to_string() }
+    }
+
+    fn wallets() -> (Wallet, Wallet) {
+        let owner = Wallet::new(SchemeId::Ed25519).expect("key generation");
+        let payee = Wallet::new(SchemeId::Secp256k1).expect("key generation");
+        (owner, payee)
+    }
+
+    #[test]
+    fn changing_an_output_breaks_an_all_signature() {
+        let (owner, payee) = wallets();
+        let (pool, first, _) = funded(&owner);
+        let transaction = Transaction::new(owner.private_key(), vec![TxInput::new(first)], vec![pay(25, &payee)]).expect("signing");
+        assert_eq!(transaction.verify(&pool), Ok(5));
+
+        // Redirected, changed in value, or with an output added, the payment no longer matches its signature
+        let mut redirected = transaction.clone();
+        redirected.outputs[0].recipient = owner.address().to_string();
+        let mut changed = transaction.clone();
+        changed.outputs[0].amount = 20;
+        let mut extended = transaction.clone();
+        extended.outputs.push(pay(1, &owner));
+        for tampered in [redirected, changed, extended] {
+            assert_eq!(tampered.verify(&pool), Err(TxError::InvalidSignature { input: 0 }));
+        }
+    }
+
+    #[test]
+    fn single_signature_commits_to_its_own_output_only() {
+        let (owner, payee) = wallets();
+        let (pool, first, second) = funded(&owner);
+        let mut transaction = Transaction::unsigned(vec![TxInput::new(first), TxInput::new(second)], vec![pay(25, &payee), pay(20, &owner)], 0);
+        for index in 0..2 {
+            transaction.sign_input(index, owner.private_key(), SighashType::SINGLE).expect("signing");
+        }
+        assert_eq!(transaction.verify(&pool), Ok(5));
+
+        // Each input only cares about the output at its own position
+        let mut first_changed = transaction.clone();
+        first_changed.outputs[0].amount = 24;
+        assert_eq!(first_changed.verify(&pool), Err(TxError::InvalidSignature { input: 0 }));
+        let mut second_changed = transaction.clone();
+        second_changed.outputs[1].amount = 19;
+        assert_eq!(second_changed.verify(&pool), Err(TxError::InvalidSignature { input: 1 }));
+        let mut extended = transaction.clone();
+        extended.outputs.push(pay(4, &payee));
+        assert_eq!(extended.verify(&pool), Ok(1));
+
+        // But still to every input, and only with an output to pair with
+        let mut reordered = transaction.clone();
+        reordered.inputs.swap(0, 1);
+        assert_eq!(reordered.verify(&pool), Err(TxError::InvalidSignature { input: 0 }));
+        transaction.outputs.pop();
+        assert_eq!(transaction.sign_input(1, owner.private_key(), SighashType::SINGLE), Err(TxError::MissingSingleOutput { input: 1 }));
+    }
+
+    #[test]
+    fn anyone_can_pay_signature_commits_to_its_own_input_only() {
+        let (owner, payee) = wallets();
+        let (pool, first, second) = funded(&owner);
+        let mut transaction = Transaction::unsigned(vec![TxInput::new(first)], vec![pay(40, &payee)], 0);
+        transaction.sign_input(0, owner.private_key(), SighashType::ALL.anyone_can_pay()).expect("signing");
+        let mut plain = transaction.clone();
+        plain.sign_input(0, owner.private_key(), SighashType::ALL).expect("signing");
+
+        // Someone else can add an input to cover the payment, which breaks a plain ALL signature
+        for pledge in [&mut transaction, &mut plain] {
+            pledge.inputs.push(TxInput::new(second.clone()));
+            pledge.sign_input(1, owner.private_key(), SighashType::ALL).expect("signing");
+        }
+        assert_eq!(transaction.verify(&pool), Ok(10));
+        assert_eq!(plain.verify(&pool), Err(TxError::InvalidSignature { input: 0 }));
+
+        // The outputs are still committed to
+        transaction.outputs[0].amount = 45;
+        assert_eq!(transaction.verify(&pool), Err(TxError::InvalidSignature { input: 0 }));
+    }
+
+    #[test]
+    fn verification_names_the_rule_broken() {
+        let (owner, payee) = wallets();
+        let (pool, first, second) = funded(&owner);
+        let spend = |inputs: Vec<&OutPoint>, outputs| {
+            let inputs = inputs.into_iter().map(|outpoint| TxInput::new(outpoint.clone())).collect();
+            Transaction::new(owner.private_key(), inputs, outputs).expect("signing")
+        };
+
+        let duplicate = spend(vec![&first, &second, &first], vec![pay(70, &payee)]);
+        assert_eq!(duplicate.verify(&pool), Err(TxError::DuplicateInput { input: 2, outpoint: first.clone() }));
+        let zero = spend(vec![&first], vec![pay(10, &payee), pay(0, &payee)]);
+        assert_eq!(zero.verify(&pool), Err(TxError::ZeroOutput { output: 1 }));
+        let overflowing = spend(vec![&first], vec![pay(u64::MAX, &payee), pay(1, &payee)]);
+        assert_eq!(overflowing.verify(&pool), Err(TxError::ValueOverflow));
+        let overspend = spend(vec![&first, &second], vec![pay(40, &payee), pay(11, &owner)]);
+        assert_eq!(overspend.verify(&pool), Err(TxError::InsufficientInputs { input_value: 50, output_value: 51 }));
+    }
+
+    #[test]
+    fn block_spending_an_output_twice_is_refused() {
+        let (owner, payee) = wallets();
+        let (mut pool, first, second) = funded(&owner);
+        let payment = Transaction::new(owner.private_key(), vec![TxInput::new(first.clone())], vec![pay(30, &payee)]).expect("signing");
+        let conflict = Transaction::new(owner.private_key(), vec![TxInput::new(second), TxInput::new(first.clone())], vec![pay(50, &owner)]).expect("signing");
+
+        // Each is valid alone, but not both in one block, and the pool is left as it was
+        let before = pool.clone();
+        let error = pool.apply_block(vec![payment.clone(), conflict]);
+        assert_eq!(error, Err((1, TxError::DoubleSpend { input: 1, outpoint: first })));
+        assert_eq!(pool, before);
+        assert_eq!(pool.apply_block(vec![payment]), Ok(0));
+    }
+
+    #[test]
+    fn spending_an_output_leaves_the_others_under_their_outpoints() {
+        let (owner, payee) = wallets();
+        let (mut pool, first, _) = funded(&owner);
+        let split = Transaction::new(owner.private_key(), vec![TxInput::new(first)], vec![pay(10, &owner), pay(20, &owner)]).expect("signing");
+        let txid = pool.apply_transaction(split);
+
+        let spend_first = Transaction::new(owner.private_key(), vec![TxInput::new(OutPoint::new(&txid, 0))], vec![pay(10, &payee)]).expect("signing");
+        assert_eq!(pool.apply_block(vec![spend_first]), Ok(0));
+
+        // vout 1 is still vout 1, not shifted into the spent slot
+        assert_eq!(pool.get_utxo(&OutPoint::new(&txid, 0)), None);
+        assert_eq!(pool.get_utxo(&OutPoint::new(&txid, 1)), Some(&pay(20, &owner)));
+        let spend_second = Transaction::new(owner.private_key(), vec![TxInput::new(OutPoint::new(&txid, 1))], vec![pay(20, &payee)]).expect("signing");
+        assert_eq!(pool.apply_block(vec![spend_second]), Ok(0));
+        assert_eq!(pool.balance_of(payee.address().as_str()), 30);
+    }
+
+    #[test]
+    fn pools_round_trip_and_must_be_sorted() {
+        let mut pool = UTXOPool::new();
+        let first = pool.credit("credit-a", "alice", 5);
+        let second = pool.credit("credit-b", "bob", 7);
+        let bytes = pool.encode();
+        assert_eq!(UTXOPool::decode(&bytes), Ok(pool.clone()));
+
+        // The same entries in the other order describe the same pool, but aren't its encoding
+        let mut unsorted = vec![];
+        crate::codec::encode_length(2, &mut unsorted);
+        for outpoint in [&second, &first] {
+            outpoint.encode_to(&mut unsorted);
+            pool.pool[outpoint].encode_to(&mut unsorted);
+        }
+        assert!(matches!(UTXOPool::decode(&unsorted), Err(DecodeError::NonCanonical(_))));
+    }
+
+    #[test]
+    fn sums_of_large_outputs_do_not_overflow() {
+        let mut pool = UTXOPool::new();
+        pool.credit("credit-a", "alice", 1);
+        pool.credit("credit-b", "alice", u64::MAX);
+        assert_eq!(pool.balance_of("alice"), u64::MAX);
+        assert_eq!(pool.balances()["alice"], u64::MAX);
+
+        // Both outputs are selected, and the change still fits an output
+        let mut undo = UtxoUndo::default();
+        pool.transfer("alice", "bob", u64::MAX - 1, 0, &mut undo).expect("covered");
+        assert_eq!(pool.balance_of("bob"), u64::MAX - 1);
+        assert_eq!(pool.balance_of("alice"), 2);
+        assert_eq!(pool.transfer("alice", "bob", u64::MAX, 1, &mut undo), None);
+    }
+}
@@ -0,0 +1,59 @@
+//! Wallets: a signing key of one signature scheme, optionally kept in an encrypted keystore file.
+use crate::address::Address; // For the address coins are sent to
+use crate::keystore::{Keystore, KeystoreError}; // For encrypted wallet files
+use crate::signing::{PrivateKey, PublicKey, SchemeId, SigningError}; // For keys of any signature scheme
+use std::path::Path; // For keystore files
+
+/// Struct representing a wallet for managing keys of one signature scheme
+#[derive(Debug)]
+pub struct Wallet {
+    /// Private key for signing transactions
+    private_key: PrivateKey,
+    /// Public key for receiving transactions
+    public_key: PublicKey,
+}
+
+/// Implementation of the Wallet struct
+impl Wallet {
+    /// Constructor for creating a new wallet with a key of the given scheme
+    pub fn new(scheme: SchemeId) -> Result<Self, SigningError> {
+        let private_key = PrivateKey::generate(scheme)?; // Generate a new private key
+        let public_key = private_key.public_key(); // Derive the public key from the private key
+        Ok(Wallet { private_key, public_key }) // Return a new Wallet instance
+    }
+
+    /// Method to save the wallet's private key to a keystore file, encrypted with a passphrase
+    ///
+    /// The scheme is recorded as the keystore's key type
+    pub fn save(&self, path: impl AsRef<Path>, passphrase: &str) -> Result<(), KeystoreError> {
+        let scheme = self.private_key.scheme();
+        Keystore::encrypt(scheme.name(), &self.private_key.to_bytes(), passphrase)?.save(path)
+    }
+
+    /// Constructor loading a wallet from a keystore file
+    pub fn load(path: impl AsRef<Path>, passphrase: &str) -> Result<Self, KeystoreError> {
+        let keystore = Keystore::load(path)?;
+        let scheme = SchemeId::from_name(&keystore.key_type)
+            .ok_or_else(|| KeystoreError::UnsupportedKeyType(keystore.key_type.clone()))?;
+        let secret = keystore.decrypt(passphrase)?;
+        let private_key = PrivateKey::from_bytes(scheme, &secret)
+            .ok_or_else(|| KeystoreError::Format(format!("not a valid {} private key", scheme.name())))?;
+        let public_key = private_key.public_key(); // Derive the public key from the private key
+        Ok(Wallet { private_key, public_key })
+    }
+
+    /// Method to get the private key that signs the wallet's transactions
+    pub fn private_key(&self) -> &PrivateKey {
+        &self.private_key
+    }
+
+    /// Method to get the public key revealed when spending from the wallet
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    /// Method to get the address coins are sent to, derived from the public key
+    pub fn address(&self) -> Address {
+        Address::from_public_key(&self.public_key)
+    }
+}